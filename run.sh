@@ -11,7 +11,5 @@ rm ./${TX_FOLDER}/*
 touch result.log
 BLUE='\033[1;34m' 
 
-echo "${BLUE}GENERATING TRANSACTIONS"
-RUST_LOG=INFO cargo run --release --  --threads 2  --count  4 | bunyan
-echo "${BLUE}SENDING TRANSACTIONS TO RELAYER"
-RUST_LOG=INFO cargo run --release --  --threads 2  --send --count  4 | bunyan
\ No newline at end of file
+echo "${BLUE}GENERATING AND SENDING TRANSACTIONS"
+RUST_LOG=INFO cargo run --release --  --mode run --threads 2  --count  4 --persist | bunyan
\ No newline at end of file
@@ -0,0 +1,239 @@
+use std::convert::TryInto;
+
+use kvdb_memorydb::InMemory as MemoryDatabase;
+use libzeropool::{
+    fawkes_crypto::{
+        engines::bn256::{Fr, Fs},
+        ff_uint::Num,
+        rand::{rngs::StdRng as ZkStdRng, Rng as ZkRng, SeedableRng},
+    },
+    native::{boundednum::BoundedNum, params::PoolBN256},
+    POOL_PARAMS,
+};
+use libzeropool_rs::{
+    client::{state::State, TransactionData, TxOutput, TxType, UserAccount},
+    keys::Keys,
+};
+use rand::{rngs::StdRng, Rng};
+use serde::Serialize;
+use tokio::time::Duration;
+
+use crate::{
+    generator::{Generator, Transaction, TxKind, WithdrawalParams},
+    prover::ProverPool,
+    retry::RetryPolicy,
+    sender::{submit_tx_timed, wait_for_job, JobResult},
+    sync::PoolSync,
+    utils::TestError,
+};
+
+#[derive(Debug, Clone)]
+pub struct ChainParams {
+    /// Receiver, native amount and energy of the withdrawal
+    pub withdrawal: WithdrawalParams,
+    /// How long to wait for a relayer job before giving up on the chain
+    pub job_timeout: Duration,
+    /// Retries of the relayer requests
+    pub retry: RetryPolicy,
+}
+
+/// A shielded account that lives across several transactions. Its state is caught up with
+/// the relayer after every mined transaction, so the next proof spends real notes against
+/// the current pool root.
+pub struct AccountChain {
+    generator: Generator,
+    prover: ProverPool,
+    index: u64,
+    rng: StdRng,
+    /// Proof randomness
+    zk_rng: ZkStdRng,
+    sk: Num<Fs>,
+    eta: Num<Fr>,
+    state: State<MemoryDatabase, PoolBN256>,
+    client: reqwest::Client,
+    relayer_url: String,
+    sync: PoolSync,
+}
+
+impl AccountChain {
+    /// Account of the `index`-th chain, derived from the generator seed. Its proofs are built
+    /// on `prover` threads and it talks to the relayer with `client`.
+    pub fn new(
+        generator: Generator,
+        prover: ProverPool,
+        client: reqwest::Client,
+        relayer_url: String,
+        index: u64,
+    ) -> Self {
+        let mut rng = generator.rng(index);
+        let sk = Num::from(rng.gen::<u64>());
+        let eta = Keys::derive(sk, &*POOL_PARAMS).eta;
+        // the generated txs of the same index use streams 2 and 3
+        let zk_rng = generator.zk_rng(index, 4);
+
+        AccountChain {
+            generator,
+            prover,
+            index,
+            rng,
+            zk_rng,
+            sk,
+            eta,
+            state: State::init_test(POOL_PARAMS.clone()),
+            sync: PoolSync::new(client.clone(), relayer_url.clone()),
+            client,
+            relayer_url,
+        }
+    }
+
+    /// Runs deposit -> transfer -> withdrawal, waiting for every job to be mined before
+    /// building the next transaction.
+    pub async fn run(&mut self, params: &ChainParams) -> Result<Vec<JobResult>, TestError> {
+        let mut results = vec![];
+
+        self.sync.sync(&mut self.state, Some(self.eta)).await?;
+
+        let amounts = self.generator.amounts().clone();
+
+        let fee = amounts.sample_fee(&mut self.rng);
+        let amount = amounts.sample_amount(&mut self.rng);
+        let tx_data = self.create_tx(TxType::Deposit(
+            BoundedNum::new(Num::from(fee)),
+            vec![],
+            BoundedNum::new(Num::from(amount)),
+        ))?;
+        let (generator, index) = (self.generator.clone(), self.index);
+        let mut rng = self.proof_rng();
+        let (file_name, deposit) = self
+            .prover
+            .run(move || generator.build_deposit(index, tx_data, &mut rng))
+            .await?;
+        results.push(self.send(TxKind::Deposit, file_name, &deposit, params).await?);
+
+        let balance = self.balance()?;
+        let fee = amounts.sample_fee(&mut self.rng);
+        let amount = balance / 2;
+        if amount + fee > balance {
+            return Err(TestError::GeneratorError(format!(
+                "insufficient balance for transfer: available {}, fee {}",
+                balance, fee
+            )));
+        }
+        let to = self.with_account(|acc| acc.generate_address());
+        let tx_data = self.create_tx(TxType::Transfer(
+            BoundedNum::new(Num::from(fee)),
+            vec![],
+            vec![TxOutput {
+                to,
+                amount: BoundedNum::new(Num::from(amount)),
+            }],
+        ))?;
+        let (file_name, transfer) = self.build_transaction(TxKind::Transfer, tx_data).await?;
+        results.push(self.send(TxKind::Transfer, file_name, &transfer, params).await?);
+
+        let balance = self.balance()?;
+        let fee = amounts.sample_fee(&mut self.rng);
+        let amount = balance.checked_sub(fee).ok_or_else(|| {
+            TestError::GeneratorError(format!(
+                "insufficient balance for withdrawal: available {}, fee {}",
+                balance, fee
+            ))
+        })?;
+        let native_amount = amounts.to_pool(params.withdrawal.native_amount)?;
+        if native_amount > amount {
+            return Err(TestError::GeneratorError(format!(
+                "native amount {} exceeds the withdrawn amount {}",
+                native_amount, amount
+            )));
+        }
+        // libzeropool-rs checks the energy against what the account accumulated
+        let tx_data = self.create_tx(TxType::Withdraw(
+            BoundedNum::new(Num::from(fee)),
+            vec![],
+            BoundedNum::new(Num::from(amount)),
+            params.withdrawal.receiver.clone(),
+            BoundedNum::new(Num::from(native_amount)),
+            BoundedNum::new(Num::from(params.withdrawal.energy)),
+        ))?;
+        let (file_name, withdrawal) = self.build_transaction(TxKind::Withdrawal, tx_data).await?;
+        results.push(self.send(TxKind::Withdrawal, file_name, &withdrawal, params).await?);
+
+        Ok(results)
+    }
+
+    /// Proves `tx_data` on the prover threads
+    async fn build_transaction(
+        &mut self,
+        kind: TxKind,
+        tx_data: TransactionData<Fr>,
+    ) -> Result<(String, Transaction), TestError> {
+        let generator = self.generator.clone();
+        let mut rng = self.proof_rng();
+        self.prover
+            .run(move || generator.build_transaction(kind, tx_data, &mut rng))
+            .await
+    }
+
+    /// Seeded randomness for the next proof, handed over to a prover thread
+    fn proof_rng(&mut self) -> ZkStdRng {
+        ZkStdRng::from_seed(self.zk_rng.gen())
+    }
+
+    /// `UserAccount` can't be kept across await points, so it is rebuilt around the
+    /// chain state for every call.
+    fn with_account<R>(
+        &mut self,
+        f: impl FnOnce(&UserAccount<MemoryDatabase, PoolBN256>) -> R,
+    ) -> R {
+        let state = std::mem::replace(&mut self.state, State::init_test(POOL_PARAMS.clone()));
+        let acc = UserAccount::new(self.sk, state, POOL_PARAMS.clone());
+        let result = f(&acc);
+        self.state = acc.state;
+        result
+    }
+
+    fn create_tx(&mut self, tx: TxType<Fr>) -> Result<TransactionData<Fr>, TestError> {
+        self.with_account(|acc| acc.create_tx(tx, None))
+            .map_err(|e| TestError::GeneratorError(e.to_string()))
+    }
+
+    fn balance(&self) -> Result<u64, TestError> {
+        self.state
+            .total_balance()
+            .try_into()
+            .map_err(|_| TestError::GeneratorError(String::from("balance overflow")))
+    }
+
+    async fn send<T: Serialize>(
+        &mut self,
+        kind: TxKind,
+        file_name: String,
+        tx: &T,
+        params: &ChainParams,
+    ) -> Result<JobResult, TestError> {
+        let submission =
+            submit_tx_timed(&self.client, &self.relayer_url, tx, &params.retry).await;
+        let job_id = submission.result?;
+        let result = JobResult::new(job_id, file_name)
+            .with_tx_type(Some(kind))
+            .with_http(submission.http)
+            .with_attempts(submission.attempts);
+
+        let status = wait_for_job(
+            &self.client,
+            &self.relayer_url,
+            job_id,
+            params.job_timeout,
+            &params.retry,
+        )
+        .await?;
+        if status.is_failed() {
+            return Err(TestError::BadResponse(format!("job {} failed", job_id)));
+        }
+        tracing::info!("job {} mined", job_id);
+
+        self.sync.sync(&mut self.state, Some(self.eta)).await?;
+
+        Ok(result)
+    }
+}
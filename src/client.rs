@@ -0,0 +1,135 @@
+//! HTTP client shared by every task talking to the relayer, so connections are pooled and
+//! reused instead of opened for every transaction.
+
+use std::{str::FromStr, time::Duration};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+
+use crate::{retry::RetryPolicy, utils::TestError};
+
+/// Everything a task needs to talk to the relayer. Clones share the connection pool.
+#[derive(Debug, Clone)]
+pub struct Relayer {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub retry: RetryPolicy,
+}
+
+/// Header sent with every request, parsed from `NAME: VALUE`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header(pub HeaderName, pub HeaderValue);
+
+impl FromStr for Header {
+    type Err = TestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            TestError::ConfigError(format!("invalid header {}: {}", s, e))
+        };
+
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| invalid(&"expected NAME: VALUE"))?;
+        let name = HeaderName::from_str(name.trim()).map_err(|e| invalid(&e))?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|e| invalid(&e))?;
+
+        Ok(Header(name, value))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    /// Timeout of a whole request, response body included
+    pub timeout: Duration,
+    pub connect_timeout: Option<Duration>,
+    /// Idle connections kept per host, unlimited if `None`
+    pub pool_max_idle: Option<usize>,
+    /// How long an idle connection is kept
+    pub pool_idle_timeout: Duration,
+    /// TCP keep-alive interval, disabled if `None`
+    pub tcp_keepalive: Option<Duration>,
+    /// Talk HTTP/2 right away instead of HTTP/1.1
+    pub http2: bool,
+    /// Proxy for every request, on top of the `HTTP_PROXY` family of variables
+    pub proxy: Option<String>,
+    pub headers: Vec<Header>,
+    /// `Authorization` header value, kept apart from `headers` as it is a secret
+    pub authorization: Option<String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            timeout: Duration::from_secs(5),
+            connect_timeout: None,
+            pool_max_idle: None,
+            pool_idle_timeout: Duration::from_secs(90),
+            tcp_keepalive: None,
+            http2: false,
+            proxy: None,
+            headers: vec![],
+            authorization: None,
+        }
+    }
+}
+
+impl HttpConfig {
+    pub fn build(&self) -> Result<reqwest::Client, TestError> {
+        let mut headers = HeaderMap::new();
+        for Header(name, value) in &self.headers {
+            headers.append(name, value.clone());
+        }
+        if let Some(authorization) = &self.authorization {
+            let mut value = HeaderValue::from_str(authorization).map_err(|e| {
+                TestError::ConfigError(format!("invalid authorization header: {}", e))
+            })?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let mut builder = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive)
+            .default_headers(headers);
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(pool_max_idle) = self.pool_max_idle {
+            builder = builder.pool_max_idle_per_host(pool_max_idle);
+        }
+        if self.http2 {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| TestError::ConfigError(format!("invalid proxy {}: {}", proxy, e)))?;
+            builder = builder.proxy(proxy);
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+#[test]
+fn http_config_test() {
+    let header: Header = "X-Api-Key: secret".parse().unwrap();
+    assert_eq!(header.0, "x-api-key");
+    assert_eq!(header.1, "secret");
+    assert!("no separator".parse::<Header>().is_err());
+
+    let config = HttpConfig {
+        headers: vec![header],
+        authorization: Some(String::from("Bearer token")),
+        pool_max_idle: Some(10),
+        http2: true,
+        ..HttpConfig::default()
+    };
+    assert!(config.build().is_ok());
+
+    let invalid_proxy = HttpConfig {
+        proxy: Some(String::from("not a url")),
+        ..HttpConfig::default()
+    };
+    assert!(invalid_proxy.build().is_err());
+}
@@ -0,0 +1,140 @@
+//! Manifest of a generated transaction corpus. Every generated file gets a JSON line in
+//! `TX_FOLDER/manifest.jsonl`, so a corpus can be inspected, ordered and filtered without
+//! opening the transactions themselves.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::TestError;
+
+pub const MANIFEST_FILE: &str = "manifest.jsonl";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    /// File name inside the corpus folder
+    pub file_name: String,
+    /// Index of the transaction in its generation run
+    pub index: u64,
+    pub tx_type: String,
+    /// Hex encoded nullifier
+    pub nullifier: String,
+    /// Address signing the deposit, empty for transfers and withdrawals
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depositor: Option<String>,
+    /// Deposited amount in pool units
+    pub amount: u64,
+    /// Fee in pool units
+    pub fee: u64,
+    pub created: SystemTime,
+    /// Keccak-256 of the transfer circuit parameters the proof was built with
+    pub params_hash: String,
+    pub seed: u64,
+}
+
+/// Appends entries to the manifest of a corpus folder
+pub struct ManifestWriter {
+    file: File,
+}
+
+impl ManifestWriter {
+    pub fn open(folder: &str) -> Result<Self, TestError> {
+        fs::create_dir_all(folder)?;
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(Path::new(folder).join(MANIFEST_FILE))?;
+
+        Ok(ManifestWriter { file })
+    }
+
+    pub fn write(&mut self, entry: &ManifestEntry) -> Result<(), TestError> {
+        let line = serde_json::to_string(entry).map_err(TestError::SerializationError)?;
+        writeln!(self.file, "{}", line)?;
+        Ok(())
+    }
+}
+
+/// Reads the manifest of a corpus folder, `None` if the corpus has none
+pub fn read(folder: &str) -> Result<Option<Vec<ManifestEntry>>, TestError> {
+    let path = Path::new(folder).join(MANIFEST_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line).map_err(TestError::SerializationError)?);
+    }
+
+    Ok(Some(entries))
+}
+
+/// Manifest entries to send: generation order, optionally narrowed to one transaction type
+/// and one seed
+pub fn select(
+    mut entries: Vec<ManifestEntry>,
+    tx_type: Option<&str>,
+    seed: Option<u64>,
+) -> Vec<ManifestEntry> {
+    entries.retain(|entry| {
+        tx_type.is_none_or(|tx_type| entry.tx_type == tx_type)
+            && seed.is_none_or(|seed| entry.seed == seed)
+    });
+    entries.sort_by_key(|entry| (entry.created, entry.index));
+    entries
+}
+
+#[test]
+fn manifest_select_test() {
+    use std::time::Duration;
+
+    let entry = |index: u64, tx_type: &str, seed: u64| ManifestEntry {
+        file_name: format!("{}.json", index),
+        index,
+        tx_type: tx_type.to_owned(),
+        nullifier: String::new(),
+        depositor: None,
+        amount: 1,
+        fee: 0,
+        created: SystemTime::UNIX_EPOCH + Duration::from_secs(10 - index),
+        params_hash: String::new(),
+        seed,
+    };
+    let entries = vec![
+        entry(1, "deposit", 1),
+        entry(2, "transfer", 1),
+        entry(3, "deposit", 1),
+        entry(4, "deposit", 2),
+    ];
+
+    let indices = |entries: Vec<ManifestEntry>| -> Vec<u64> {
+        entries.iter().map(|entry| entry.index).collect()
+    };
+
+    assert_eq!(
+        indices(select(entries.clone(), None, None)),
+        vec![4, 3, 2, 1]
+    );
+    assert_eq!(
+        indices(select(entries.clone(), Some("deposit"), Some(1))),
+        vec![3, 1]
+    );
+
+    let line = serde_json::to_string(&entries[0]).unwrap();
+    assert_eq!(
+        serde_json::from_str::<ManifestEntry>(&line).unwrap(),
+        entries[0]
+    );
+}
@@ -0,0 +1,156 @@
+//! Depositor keys: the Ethereum accounts signing deposit nullifiers. Several keys spread the
+//! deposit load across addresses the way real traffic does.
+
+use std::{fs, str::FromStr};
+
+use hmac::{Hmac, Mac};
+use secp256k1::{PublicKey, SecretKey};
+use sha2::Sha512;
+
+use crate::{signing::SECP256K1, utils::TestError};
+
+/// Ethereum account path used by ganache, metamask and friends, the account index is appended
+const ETH_PATH: [u32; 4] = [44 | HARDENED, 60 | HARDENED, HARDENED, 0];
+const HARDENED: u32 = 1 << 31;
+
+/// How depositor keys are assigned to generated transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAssignment {
+    /// The `i`-th transaction is signed with key `i % keys`
+    RoundRobin,
+    /// Every transaction draws its key from the seeded generator randomness
+    Random,
+}
+
+impl FromStr for KeyAssignment {
+    type Err = TestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(KeyAssignment::RoundRobin),
+            "random" => Ok(KeyAssignment::Random),
+            _ => Err(TestError::ConfigError(format!(
+                "unknown key assignment {}, expected round-robin or random",
+                s
+            ))),
+        }
+    }
+}
+
+/// Derives the first `count` accounts of a BIP-39 mnemonic at `m/44'/60'/0'/0/i`
+pub fn from_mnemonic(phrase: &str, count: u32) -> Result<Vec<SecretKey>, TestError> {
+    let mnemonic = bip39::Mnemonic::parse(phrase)
+        .map_err(|e| TestError::ConfigError(format!("invalid mnemonic: {}", e)))?;
+    let seed = mnemonic.to_seed("");
+
+    let (key, chain_code) = ETH_PATH
+        .iter()
+        .try_fold(master_key(&seed)?, |(key, chain_code), index| {
+            child_key(&key, &chain_code, *index)
+        })?;
+
+    (0..count)
+        .map(|index| child_key(&key, &chain_code, index).map(|(key, _)| key))
+        .collect()
+}
+
+/// Reads hex encoded keys, one per line. Empty lines and lines starting with `#` are skipped.
+pub fn from_file(path: &str) -> Result<Vec<SecretKey>, TestError> {
+    let content = fs::read_to_string(path)?;
+
+    let keys = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            SecretKey::from_str(line.trim_start_matches("0x"))
+                .map_err(|e| TestError::ConfigError(format!("invalid key in {}: {}", path, e)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if keys.is_empty() {
+        return Err(TestError::ConfigError(format!("no keys in {}", path)));
+    }
+
+    Ok(keys)
+}
+
+fn master_key(seed: &[u8]) -> Result<(SecretKey, [u8; 32]), TestError> {
+    split(hmac_sha512(b"Bitcoin seed", &[seed]))
+}
+
+/// BIP-32 private parent key to private child key derivation
+fn child_key(
+    key: &SecretKey,
+    chain_code: &[u8; 32],
+    index: u32,
+) -> Result<(SecretKey, [u8; 32]), TestError> {
+    let hash = if index & HARDENED != 0 {
+        hmac_sha512(chain_code, &[&[0], &key[..], &index.to_be_bytes()])
+    } else {
+        let public_key = PublicKey::from_secret_key(&SECP256K1, key).serialize();
+        hmac_sha512(chain_code, &[&public_key, &index.to_be_bytes()])
+    };
+
+    let (mut child, chain_code) = split(hash)?;
+    child.add_assign(&key[..]).map_err(invalid_key)?;
+
+    Ok((child, chain_code))
+}
+
+fn split(hash: [u8; 64]) -> Result<(SecretKey, [u8; 32]), TestError> {
+    let key = SecretKey::from_slice(&hash[..32]).map_err(invalid_key)?;
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(&hash[32..]);
+
+    Ok((key, chain_code))
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("hmac accepts any key length");
+    for chunk in data {
+        mac.update(chunk);
+    }
+
+    mac.finalize().into_bytes().into()
+}
+
+fn invalid_key(e: secp256k1::Error) -> TestError {
+    TestError::GeneratorError(format!("derived key is invalid: {}", e))
+}
+
+#[test]
+fn ganache_mnemonic_test() {
+    // accounts listed in misc/index.js
+    let keys = from_mnemonic(
+        "myth like bonus scare over problem client lizard pioneer submit female collect",
+        3,
+    )
+    .unwrap();
+
+    assert_eq!(
+        keys,
+        vec![
+            SecretKey::from_str("4f3edf983ac636a65a842ce7c78d9aa706d3b113bce9c46f30d7d21715b23b1d")
+                .unwrap(),
+            SecretKey::from_str("6cbed15c793ce57650b9877cf6fa156fbef513c4e6134f022a85b1ffdd59b2a1")
+                .unwrap(),
+            SecretKey::from_str("6370fd033278c143179d81c5526140625662b8daa446c22ee2d73db3707e620c")
+                .unwrap(),
+        ]
+    );
+    assert!(from_mnemonic("myth like bonus", 1).is_err());
+}
+
+#[test]
+fn key_assignment_test() {
+    assert_eq!(
+        "round-robin".parse::<KeyAssignment>().unwrap(),
+        KeyAssignment::RoundRobin
+    );
+    assert_eq!(
+        "random".parse::<KeyAssignment>().unwrap(),
+        KeyAssignment::Random
+    );
+    assert!("sticky".parse::<KeyAssignment>().is_err());
+}
@@ -3,6 +3,7 @@ use libzeropool::{
         backend::bellman_groth16::{engines::Bn256, prover},
         engines::bn256::Fr,
         ff_uint::Num,
+        rand::{self as zk_rand, Rng as ZkRng},
     },
     native::boundednum::BoundedNum,
 };
@@ -10,19 +11,31 @@ use serde::{Deserialize, Serialize};
 
 use libzeropool::fawkes_crypto::backend::bellman_groth16::verifier::VK;
 use libzeropool::fawkes_crypto::backend::bellman_groth16::{verifier::verify, Parameters};
-use libzeropool::POOL_PARAMS;
-use libzeropool_rs::proof::prove_tx;
+use libzeropool::{constants, native::params::PoolBN256, POOL_PARAMS};
 
-use libzeropool_rs::client::{state::State, TxType, UserAccount};
-use rand::Rng;
+use libzeropool_rs::client::{state::State, TransactionData, TxOutput, TxType, UserAccount};
+use kvdb_memorydb::InMemory as MemoryDatabase;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use secp256k1::SecretKey;
+use web3::signing::keccak256;
 
-use std::{env, thread};
+use std::{
+    convert::TryInto,
+    env,
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+    time::SystemTime,
+};
 use std::{fs, str::FromStr};
-use web3::{api::Accounts, types::SignedData};
-
-use crate::utils::TestError;
+use crate::{
+    corpus::ManifestEntry,
+    depositors::KeyAssignment,
+    seeded,
+    signing::{self, Signature},
+    sync::PoolSnapshot,
+    utils::TestError,
+};
 
 #[derive(Serialize, Deserialize)]
 struct Proof {
@@ -39,10 +52,218 @@ pub struct Deposit {
     deposit_signature: String,
 }
 
+/// Relayer payload for transfers and withdrawals, which carry no deposit signature
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transaction {
+    proof: Proof,
+    pub memo: String,
+    tx_type: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxKind {
+    Deposit,
+    Transfer,
+    Withdrawal,
+}
+
+impl TxKind {
+    /// `txType` code the relayer expects for this kind of transaction
+    pub fn code(&self) -> &'static str {
+        match self {
+            TxKind::Deposit => "0000",
+            TxKind::Transfer => "0001",
+            TxKind::Withdrawal => "0002",
+        }
+    }
+
+    /// Kind of a relayer payload from its `txType` code
+    pub fn from_code(code: &str) -> Option<Self> {
+        [TxKind::Deposit, TxKind::Transfer, TxKind::Withdrawal]
+            .into_iter()
+            .find(|kind| kind.code() == code)
+    }
+
+    /// Name accepted by `FromStr`
+    pub fn name(&self) -> &'static str {
+        match self {
+            TxKind::Deposit => "deposit",
+            TxKind::Transfer => "transfer",
+            TxKind::Withdrawal => "withdrawal",
+        }
+    }
+}
+
+/// Proved transaction, ready to be sent or saved to a corpus
+#[derive(Debug, Clone)]
+pub struct ProvedTx {
+    pub kind: TxKind,
+    pub entry: ManifestEntry,
+    /// Relayer payload
+    pub payload: serde_json::Value,
+}
+
+/// Transaction built from an account, before proving
+pub struct TxDraft {
+    pub tx_data: TransactionData<Fr>,
+    /// Deposited amount in pool units
+    pub amount: u64,
+    /// Fee in pool units
+    pub fee: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct WithdrawalParams {
+    /// 20-byte address receiving the withdrawn tokens
+    pub receiver: Vec<u8>,
+    /// Token amount swapped to native coins on withdrawal
+    pub native_amount: u64,
+    pub energy: u64,
+}
+
+/// Inclusive range of token amounts, parsed from either `N` or `MIN..MAX`.
+/// A value is drawn from it for every generated transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountRange {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl AmountRange {
+    pub fn fixed(amount: u64) -> Self {
+        AmountRange {
+            min: amount,
+            max: amount,
+        }
+    }
+
+    /// Draws a whole number of `unit`s, every one in the range equally likely
+    fn sample<R: Rng>(&self, unit: u64, rng: &mut R) -> u64 {
+        rng.gen_range(self.min / unit..=self.max / unit)
+    }
+}
+
+impl FromStr for AmountRange {
+    type Err = TestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| TestError::ConfigError(format!("invalid amount {}: {}", s, e)))
+        };
+
+        let range = match s.split_once("..") {
+            Some((min, max)) => AmountRange {
+                min: parse(min)?,
+                max: parse(max)?,
+            },
+            None => AmountRange::fixed(parse(s)?),
+        };
+
+        if range.min > range.max {
+            return Err(TestError::ConfigError(format!("empty amount range {}", s)));
+        }
+
+        Ok(range)
+    }
+}
+
+/// Token amounts of generated transactions. Amounts are given in token units and converted
+/// to pool units with the pool `denominator`, so both have to be divisible by it.
+#[derive(Debug, Clone)]
+pub struct TxAmounts {
+    amount: AmountRange,
+    fee: AmountRange,
+    denominator: u64,
+}
+
+impl Default for TxAmounts {
+    /// One pool unit deposits without fee, with the denominator of the test pool
+    fn default() -> Self {
+        TxAmounts {
+            amount: AmountRange::fixed(DEFAULT_DENOMINATOR),
+            fee: AmountRange::fixed(0),
+            denominator: DEFAULT_DENOMINATOR,
+        }
+    }
+}
+
+impl TxAmounts {
+    pub fn new(amount: AmountRange, fee: AmountRange, denominator: u64) -> Result<Self, TestError> {
+        if denominator == 0 {
+            return Err(TestError::ConfigError(String::from(
+                "denominator must be positive",
+            )));
+        }
+
+        let amounts = TxAmounts {
+            amount,
+            fee,
+            denominator,
+        };
+        for value in [amount.min, amount.max, fee.min, fee.max] {
+            amounts.to_pool(value)?;
+        }
+
+        Ok(amounts)
+    }
+
+    /// Converts a token amount to pool units
+    pub fn to_pool(&self, amount: u64) -> Result<u64, TestError> {
+        if !amount.is_multiple_of(self.denominator) {
+            return Err(TestError::ConfigError(format!(
+                "amount {} is not a multiple of the denominator {}",
+                amount, self.denominator
+            )));
+        }
+
+        Ok(amount / self.denominator)
+    }
+
+    /// Draws a deposit amount in pool units
+    pub fn sample_amount<R: Rng>(&self, rng: &mut R) -> u64 {
+        self.amount.sample(self.denominator, rng)
+    }
+
+    /// Draws a fee in pool units
+    pub fn sample_fee<R: Rng>(&self, rng: &mut R) -> u64 {
+        self.fee.sample(self.denominator, rng)
+    }
+}
+
+impl WithdrawalParams {
+    pub fn parse_receiver(address: &str) -> Result<Vec<u8>, TestError> {
+        let receiver = hex::decode(address.trim_start_matches("0x"))
+            .map_err(|e| TestError::ConfigError(format!("invalid receiver address: {}", e)))?;
+
+        if receiver.len() != 20 {
+            return Err(TestError::ConfigError(format!(
+                "invalid receiver address: expected 20 bytes, got {}",
+                receiver.len()
+            )));
+        }
+
+        Ok(receiver)
+    }
+}
+
+/// Denominator of the test pool token, see `misc/index.js`
+pub const DEFAULT_DENOMINATOR: u64 = 1_000_000_000;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Generator {
-    sk: SecretKey,
+    keys: Arc<Vec<SecretKey>>,
+    assignment: KeyAssignment,
+    pool: Option<Arc<PoolSnapshot>>,
+    amounts: TxAmounts,
+    params: Arc<ProvingParams>,
+    seed: u64,
+    /// Corpus folder generated transactions are saved to, `TX_FOLDER` if `None`
+    folder: Option<String>,
 }
 
 fn serialize(num: Num<Fr>) -> Result<[u8; 32], TestError> {
@@ -57,119 +278,568 @@ fn serialize(num: Num<Fr>) -> Result<[u8; 32], TestError> {
     Ok(buf)
 }
 
-fn pack_signature(signature: &SignedData) -> Result<String, TestError> {
+fn pack_signature(signature: &Signature) -> Result<String, TestError> {
     let mut packed = String::from("0x");
-    packed.push_str(&hex::encode(signature.r.as_bytes()));
+    packed.push_str(&hex::encode(signature.r));
 
-    let mut s_bytes: [u8; 32] = [0; 32];
-    s_bytes.copy_from_slice(signature.s.as_bytes());
-    if signature.v % 2 == 0 {
+    let mut s_bytes: [u8; 32] = signature.s;
+    if signature.v.is_multiple_of(2) {
         let first_byte = s_bytes.first_mut().unwrap();
         *first_byte ^= 0b1000_0000;
     }
 
     packed.push_str(&hex::encode(s_bytes));
     tracing::trace!(
-        "Signature:\nv:{},\n{:#?}\n{:#?},\n{:#?}",
+        "Signature:\nv:{},\n{}\n{},\n{:#?}",
         signature.v,
-        &signature.r,
-        &signature.s,
+        hex::encode(signature.r),
+        hex::encode(signature.s),
         &packed
     );
 
     Ok(packed)
 }
 
-impl Generator {
-    pub fn new(key: &str) -> Self {
-        let sk: secp256k1::SecretKey = SecretKey::from_str(key).unwrap();
-        Generator { sk }
-    }
+impl FromStr for TxKind {
+    type Err = TestError;
 
-    fn sign(&self, buf: [u8; 32]) -> SignedData {
-        let transport = web3::transports::Http::new("http://localhost:8545").unwrap();
-        let web3 = web3::Web3::new(transport);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposit" => Ok(TxKind::Deposit),
+            "transfer" => Ok(TxKind::Transfer),
+            "withdrawal" => Ok(TxKind::Withdrawal),
+            _ => Err(TestError::GeneratorError(String::from(
+                "unknown transaction type",
+            ))),
+        }
+    }
+}
 
-        // Insert the 32-byte private key in hex format (do NOT prefix with 0x)
-        // let sk: secp256k1::SecretKey = SecretKey::from_str(CLIENT_PK).unwrap();
+/// Transfer circuit parameters and verification key. They are read from
+/// `TRANSFER_PARAMS_PATH` and `VK_PATH` on the first proof and shared by every
+/// generator cloned from the same one.
+pub struct ProvingParams {
+    params_path: String,
+    vk_path: String,
+    loaded: OnceLock<LoadedParams>,
+    load_lock: Mutex<()>,
+}
 
-        let key_ref = web3::signing::SecretKeyRef::new(&self.sk);
+struct LoadedParams {
+    params: Parameters<Bn256>,
+    vk: VK<Bn256>,
+    /// Keccak-256 of the parameters file
+    hash: [u8; 32],
+}
 
-        let accounts = web3.accounts();
-        let signed = Accounts::sign(&accounts, buf, key_ref);
-        signed
+impl ProvingParams {
+    pub fn new(params_path: String, vk_path: String) -> Self {
+        ProvingParams {
+            params_path,
+            vk_path,
+            loaded: OnceLock::new(),
+            load_lock: Mutex::new(()),
+        }
     }
 
-pub async fn generate_deposit(self) -> Result<(String,String), TestError> {
-        let state = State::init_test(POOL_PARAMS.clone());
-        let acc = UserAccount::new(
-            Num::from(rand::thread_rng().gen::<u64>()),
-            state,
-            POOL_PARAMS.clone(),
-        );
-
-        let tx_data = acc
-            .create_tx(
-                TxType::Deposit(
-                    BoundedNum::new(Num::ZERO),
-                    vec![],
-                    BoundedNum::new(Num::ONE),
-                ),
-                None,
-            )
-            .unwrap();
-
+    pub fn from_env() -> Self {
         let params_path = std::env::var("TRANSFER_PARAMS_PATH")
             .unwrap_or(String::from("../params/transfer_params.bin"));
 
         let vk_path = std::env::var("VK_PATH")
             .unwrap_or(String::from("../params/transfer_verification_key.json"));
 
-        let params_data = std::fs::read(params_path).unwrap();
+        Self::new(params_path, vk_path)
+    }
+
+    fn get(&self) -> Result<&LoadedParams, TestError> {
+        if let Some(loaded) = self.loaded.get() {
+            return Ok(loaded);
+        }
+
+        // make concurrent generators wait for a single read instead of racing
+        let _guard = self.load_lock.lock().unwrap();
+        if let Some(loaded) = self.loaded.get() {
+            return Ok(loaded);
+        }
+
+        tracing::info!("loading proving parameters from {}", self.params_path);
+        let params_data = std::fs::read(&self.params_path)?;
+        let hash = keccak256(&params_data);
         let mut params_data_cur = &params_data[..];
 
-        let params = Parameters::<Bn256>::read(&mut params_data_cur, false, false).unwrap();
+        let params = Parameters::<Bn256>::read(&mut params_data_cur, false, false)?;
+
+        let vk_str = std::fs::read_to_string(&self.vk_path)?;
+
+        let vk: VK<Bn256> =
+            serde_json::from_str(&vk_str).map_err(TestError::SerializationError)?;
+
+        Ok(self.loaded.get_or_init(|| LoadedParams { params, vk, hash }))
+    }
+
+    /// Hex encoded Keccak-256 of the parameters file, loading it if needed
+    pub fn hash(&self) -> Result<String, TestError> {
+        Ok(hex::encode(self.get()?.hash))
+    }
+
+    fn prove<R: ZkRng>(&self, tx_data: TransactionData<Fr>, rng: &mut R) -> Result<Proof, TestError> {
+        let LoadedParams { params, vk, .. } = self.get()?;
+
+        let (inputs, proof) = seeded::prove_tx(params, &tx_data.public, &tx_data.secret, rng)?;
+
+        if !verify(vk, &proof, &inputs) {
+            return Err(TestError::GeneratorError(String::from(
+                "generated proof failed verification",
+            )));
+        }
+
+        Ok(Proof { inputs, proof })
+    }
+}
+
+/// libzeropool-rs doesn't account for the fee when checking the transfer balance,
+/// so a fee the account can't cover would only fail in the prover.
+fn check_balance(
+    acc: &UserAccount<MemoryDatabase, PoolBN256>,
+    fee: u64,
+) -> Result<(), TestError> {
+    let balance: u64 = acc
+        .state
+        .total_balance()
+        .try_into()
+        .map_err(|_| TestError::GeneratorError(String::from("balance overflow")))?;
+    if fee > balance {
+        return Err(TestError::GeneratorError(format!(
+            "insufficient balance for fee: available {}, fee {}",
+            balance, fee
+        )));
+    }
+
+    Ok(())
+}
+
+fn save_tx<T: Serialize>(
+    folder: Option<&str>,
+    file_name: String,
+    tx: &T,
+) -> Result<(String, String), TestError> {
+    match folder.map_or_else(|| env::var("TX_FOLDER"), |folder| Ok(folder.to_owned())) {
+        Ok(mut tx_folder) => {
+            if tx_folder.ends_with('/') {
+                tx_folder.pop();
+            }
+            let thread_name = thread::current().name().unwrap_or_default().to_owned();
+            let serialized_tx = serde_json::to_string(tx).map_err(TestError::SerializationError)?;
+            let path = format!("{}/{}.json", tx_folder, file_name);
+            fs::write(path, serialized_tx)?;
+            Ok((file_name, thread_name))
+        }
+        Err(_) => Err(TestError::ConfigError(String::from("TX_FOLDER not set"))),
+    }
+}
+
+impl Generator {
+    pub fn new(key: &str) -> Self {
+        let sk: secp256k1::SecretKey = SecretKey::from_str(key).unwrap();
+        Generator {
+            keys: Arc::new(vec![sk]),
+            assignment: KeyAssignment::RoundRobin,
+            pool: None,
+            amounts: TxAmounts::default(),
+            params: Arc::new(ProvingParams::from_env()),
+            seed: rand::thread_rng().gen(),
+            folder: None,
+        }
+    }
 
-        let nullifier: Num<libzeropool::fawkes_crypto::engines::bn256::Fr> =
-            tx_data.public.nullifier;
-        let (inputs, proof) = prove_tx(&params, &*POOL_PARAMS, tx_data.public, tx_data.secret);
+    /// Saves generated transactions to `folder` instead of `TX_FOLDER`
+    pub fn with_folder(mut self, folder: String) -> Self {
+        self.folder = Some(folder);
+        self
+    }
 
-        let vk_str = std::fs::read_to_string(vk_path).unwrap();
+    /// Makes the accounts and amounts of generated transactions depend on `seed` and the
+    /// transaction index only
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
 
-        let vk: VK<Bn256> = serde_json::from_str(&vk_str).unwrap();
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 
-        let verification_result = verify(&vk, &proof, &inputs);
+    /// Shares already loaded proving parameters with this generator
+    pub fn with_params(mut self, params: Arc<ProvingParams>) -> Self {
+        self.params = params;
+        self
+    }
 
-        assert!(verification_result);
+    pub fn with_amounts(mut self, amounts: TxAmounts) -> Self {
+        self.amounts = amounts;
+        self
+    }
 
-        let nullifier_bytes = serialize(nullifier)?;
+    pub fn amounts(&self) -> &TxAmounts {
+        &self.amounts
+    }
 
-        let deposit_signature = self.sign(nullifier_bytes);
+    /// Builds transactions on top of the synced pool instead of an empty tree
+    pub fn with_pool(mut self, pool: Arc<PoolSnapshot>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Signs deposits with `keys` instead of the single key the generator was created with
+    pub fn with_keys(
+        mut self,
+        keys: Vec<SecretKey>,
+        assignment: KeyAssignment,
+    ) -> Result<Self, TestError> {
+        if keys.is_empty() {
+            return Err(TestError::ConfigError(String::from("no depositor keys")));
+        }
+        self.keys = Arc::new(keys);
+        self.assignment = assignment;
+        Ok(self)
+    }
+
+    pub fn keys(&self) -> &[SecretKey] {
+        &self.keys
+    }
+
+    /// Ethereum address of the first depositor key
+    pub fn address(&self) -> Vec<u8> {
+        signing::address(&self.keys[0]).to_vec()
+    }
+
+    /// Depositor key of the `index`-th transaction
+    fn key(&self, index: u64) -> &SecretKey {
+        let len = self.keys.len() as u64;
+        let key = match self.assignment {
+            KeyAssignment::RoundRobin => index % len,
+            // a separate stream, so keys don't follow the throwaway account keys
+            KeyAssignment::Random => self.stream_rng(index, 1).gen_range(0..len),
+        };
+        &self.keys[key as usize]
+    }
+
+    fn sign(&self, index: u64, buf: [u8; 32]) -> Signature {
+        signing::sign(self.key(index), buf)
+    }
+
+    /// Proves a deposit and signs its nullifier with the depositor key of the `index`-th
+    /// transaction. Returns the hex encoded nullifier together with the relayer payload.
+    pub(crate) fn build_deposit<R: ZkRng>(
+        &self,
+        index: u64,
+        tx_data: TransactionData<Fr>,
+        rng: &mut R,
+    ) -> Result<(String, Deposit), TestError> {
+        let nullifier_bytes = serialize(tx_data.public.nullifier)?;
+        let memo = hex::encode(&tx_data.memo);
+
+        let proof = self.params.prove(tx_data, rng)?;
+
+        let deposit_signature = self.sign(index, nullifier_bytes);
 
         let packed_sig = pack_signature(&deposit_signature)?;
 
         let deposit = Deposit {
-            proof: Proof { inputs, proof },
-            memo: hex::encode(tx_data.memo),
-            tx_type: String::from("0000"),
+            proof,
+            memo,
+            tx_type: TxKind::Deposit.code().to_owned(),
             deposit_signature: packed_sig,
         };
 
-        match env::var("TX_FOLDER") {
-            Ok(mut tx_folder) => {
-                if tx_folder.ends_with("/") {
-                    tx_folder.pop();
-                }
-                    let file_name = hex::encode(nullifier_bytes);
-                    let thread_name = thread::current().name().unwrap().to_owned();
-                    let serialized_deposit = serde_json::to_string(&deposit).unwrap();//TODO: error conversion impl
-                    let path = format!("{}/{}.json", tx_folder, file_name);
-                    fs::write(path, serialized_deposit)?;
-                    Ok((file_name,thread_name))
-            }
-            Err(_) => Err(TestError::ConfigError(String::from("TX_FOLDER not set"))),
+        Ok((hex::encode(nullifier_bytes), deposit))
+    }
+
+    /// Proves a transfer or a withdrawal. Returns the hex encoded nullifier together with the
+    /// relayer payload.
+    pub(crate) fn build_transaction<R: ZkRng>(
+        &self,
+        kind: TxKind,
+        tx_data: TransactionData<Fr>,
+        rng: &mut R,
+    ) -> Result<(String, Transaction), TestError> {
+        let nullifier_bytes = serialize(tx_data.public.nullifier)?;
+        let memo = hex::encode(&tx_data.memo);
+
+        let proof = self.params.prove(tx_data, rng)?;
+
+        let tx = Transaction {
+            proof,
+            memo,
+            tx_type: kind.code().to_owned(),
+        };
+
+        Ok((hex::encode(nullifier_bytes), tx))
+    }
+
+    /// Randomness of the `index`-th transaction, derived from the generator seed only
+    pub fn rng(&self, index: u64) -> StdRng {
+        self.stream_rng(index, 0)
+    }
+
+    fn stream_rng(&self, index: u64, stream: u64) -> StdRng {
+        let mut seed = [0u8; 32];
+        seed[..8].copy_from_slice(&self.seed.to_le_bytes());
+        seed[8..16].copy_from_slice(&index.to_le_bytes());
+        seed[16..24].copy_from_slice(&stream.to_le_bytes());
+        StdRng::from_seed(seed)
+    }
+
+    /// Randomness the notes, memo encryption and proof of the `index`-th transaction are
+    /// built with. libzeropool uses an older `rand`, so it gets its own generator.
+    pub(crate) fn zk_rng(&self, index: u64, stream: u64) -> zk_rand::rngs::StdRng {
+        zk_rand::SeedableRng::from_seed(self.stream_rng(index, stream).gen())
+    }
+
+    fn throwaway_account<R: Rng>(&self, rng: &mut R) -> UserAccount<MemoryDatabase, PoolBN256> {
+        let state = match &self.pool {
+            Some(pool) => pool.state(),
+            None => State::init_test(POOL_PARAMS.clone()),
+        };
+        UserAccount::new(Num::from(rng.gen::<u64>()), state, POOL_PARAMS.clone())
+    }
+
+    /// Deposit of the `index`-th transaction, before proving
+    pub fn create_deposit(&self, index: u64) -> Result<TxDraft, TestError> {
+        let mut rng = self.rng(index);
+        let acc = self.throwaway_account(&mut rng);
+        let fee = self.amounts.sample_fee(&mut rng);
+        let amount = self.amounts.sample_amount(&mut rng);
+
+        let tx_data = seeded::create_tx(
+            &acc,
+            TxType::Deposit(
+                BoundedNum::new(Num::from(fee)),
+                vec![],
+                BoundedNum::new(Num::from(amount)),
+            ),
+            &mut self.zk_rng(index, 2),
+        )?;
+
+        Ok(TxDraft {
+            tx_data,
+            amount,
+            fee,
+        })
+    }
+
+    /// Private transfer of the `index`-th transaction with `outputs` zero-value notes sent to
+    /// fresh addresses of the throwaway account, so it does not need any balance to be spent.
+    pub fn create_transfer(
+        &self,
+        index: u64,
+        outputs: usize,
+    ) -> Result<TxDraft, TestError> {
+        // libzeropool-rs keeps the last output slot for the account itself
+        if outputs >= constants::OUT {
+            return Err(TestError::GeneratorError(format!(
+                "too many outputs: expected {} max, got {}",
+                constants::OUT - 1,
+                outputs
+            )));
+        }
+
+        let mut rng = self.rng(index);
+        let acc = self.throwaway_account(&mut rng);
+        let fee = self.amounts.sample_fee(&mut rng);
+        check_balance(&acc, fee)?;
+
+        let mut zk_rng = self.zk_rng(index, 2);
+        let tx_outputs = (0..outputs)
+            .map(|_| TxOutput {
+                to: seeded::generate_address(&acc, &mut zk_rng),
+                amount: BoundedNum::new(Num::ZERO),
+            })
+            .collect();
+
+        let tx_data = seeded::create_tx(
+            &acc,
+            TxType::Transfer(BoundedNum::new(Num::from(fee)), vec![], tx_outputs),
+            &mut zk_rng,
+        )?;
+
+        Ok(TxDraft {
+            tx_data,
+            amount: 0,
+            fee,
+        })
+    }
+
+    /// Zero-amount withdrawal of the `index`-th transaction. The fee, energy and native amount
+    /// have to be covered by the throwaway account, so only zero values pass for a fresh
+    /// account.
+    pub fn create_withdrawal(
+        &self,
+        index: u64,
+        params: &WithdrawalParams,
+    ) -> Result<TxDraft, TestError> {
+        let mut rng = self.rng(index);
+        let acc = self.throwaway_account(&mut rng);
+        let fee = self.amounts.sample_fee(&mut rng);
+        check_balance(&acc, fee)?;
+        let native_amount = self.amounts.to_pool(params.native_amount)?;
+        // the native amount is swapped out of the withdrawn tokens
+        let amount = 0;
+        if native_amount > amount {
+            return Err(TestError::GeneratorError(format!(
+                "native amount {} exceeds the withdrawn amount {}",
+                native_amount, amount
+            )));
+        }
+
+        let tx_data = seeded::create_tx(
+            &acc,
+            TxType::Withdraw(
+                BoundedNum::new(Num::from(fee)),
+                vec![],
+                BoundedNum::new(Num::from(amount)),
+                params.receiver.clone(),
+                BoundedNum::new(Num::from(native_amount)),
+                BoundedNum::new(Num::from(params.energy)),
+            ),
+            &mut self.zk_rng(index, 2),
+        )?;
+
+        Ok(TxDraft {
+            tx_data,
+            amount: 0,
+            fee,
+        })
+    }
+
+    pub async fn generate_deposit(self, index: u64) -> Result<ManifestEntry, TestError> {
+        let proved = self.prove_deposit(index)?;
+        self.save(&proved)?;
+        Ok(proved.entry)
+    }
+
+    pub async fn generate_transfer(
+        self,
+        index: u64,
+        outputs: usize,
+    ) -> Result<ManifestEntry, TestError> {
+        let proved = self.prove_transfer(index, outputs)?;
+        self.save(&proved)?;
+        Ok(proved.entry)
+    }
+
+    pub async fn generate_withdrawal(
+        self,
+        index: u64,
+        params: &WithdrawalParams,
+    ) -> Result<ManifestEntry, TestError> {
+        let proved = self.prove_withdrawal(index, params)?;
+        self.save(&proved)?;
+        Ok(proved.entry)
+    }
+
+    /// Proves the `index`-th transaction of `kind`. `outputs` only applies to transfers and
+    /// `withdrawal` to withdrawals.
+    pub fn prove(
+        &self,
+        kind: TxKind,
+        index: u64,
+        outputs: usize,
+        withdrawal: &WithdrawalParams,
+    ) -> Result<ProvedTx, TestError> {
+        match kind {
+            TxKind::Deposit => self.prove_deposit(index),
+            TxKind::Transfer => self.prove_transfer(index, outputs),
+            TxKind::Withdrawal => self.prove_withdrawal(index, withdrawal),
         }
     }
+
+    pub fn prove_deposit(&self, index: u64) -> Result<ProvedTx, TestError> {
+        let draft = self.create_deposit(index)?;
+        let (amount, fee) = (draft.amount, draft.fee);
+
+        let (nullifier, deposit) = self.build_deposit(index, draft.tx_data, &mut self.zk_rng(index, 3))?;
+
+        self.proved(TxKind::Deposit, index, amount, fee, nullifier, &deposit)
+    }
+
+    pub fn prove_transfer(&self, index: u64, outputs: usize) -> Result<ProvedTx, TestError> {
+        let draft = self.create_transfer(index, outputs)?;
+        let (amount, fee) = (draft.amount, draft.fee);
+
+        let (nullifier, transfer) = self.build_transaction(
+            TxKind::Transfer,
+            draft.tx_data,
+            &mut self.zk_rng(index, 3),
+        )?;
+
+        self.proved(TxKind::Transfer, index, amount, fee, nullifier, &transfer)
+    }
+
+    pub fn prove_withdrawal(
+        &self,
+        index: u64,
+        params: &WithdrawalParams,
+    ) -> Result<ProvedTx, TestError> {
+        let draft = self.create_withdrawal(index, params)?;
+        let (amount, fee) = (draft.amount, draft.fee);
+
+        let (nullifier, withdrawal) =
+            self.build_transaction(TxKind::Withdrawal, draft.tx_data, &mut self.zk_rng(index, 3))?;
+
+        self.proved(TxKind::Withdrawal, index, amount, fee, nullifier, &withdrawal)
+    }
+
+    /// Saves a proved transaction to the corpus folder, its manifest entry is left to the
+    /// caller
+    pub fn save(&self, proved: &ProvedTx) -> Result<(), TestError> {
+        let (nullifier, thread_name) = save_tx(
+            self.folder.as_deref(),
+            proved.entry.nullifier.clone(),
+            &proved.payload,
+        )?;
+        tracing::debug!("{} saved {}", thread_name, nullifier);
+        Ok(())
+    }
+
+    /// Describes a proved transaction for the corpus manifest
+    fn proved<T: Serialize>(
+        &self,
+        kind: TxKind,
+        index: u64,
+        amount: u64,
+        fee: u64,
+        nullifier: String,
+        tx: &T,
+    ) -> Result<ProvedTx, TestError> {
+        let depositor = match kind {
+            TxKind::Deposit => Some(format!(
+                "0x{}",
+                hex::encode(signing::address(self.key(index)))
+            )),
+            _ => None,
+        };
+
+        let entry = ManifestEntry {
+            file_name: format!("{}.json", nullifier),
+            index,
+            tx_type: kind.name().to_owned(),
+            nullifier,
+            depositor,
+            amount,
+            fee,
+            created: SystemTime::now(),
+            params_hash: self.params.hash()?,
+            seed: self.seed,
+        };
+
+        Ok(ProvedTx {
+            kind,
+            entry,
+            payload: serde_json::to_value(tx).map_err(TestError::SerializationError)?,
+        })
+    }
 }
 
 #[test]
@@ -185,7 +855,7 @@ fn nullifier_sign_test() {
 
     let deposit_signature =
         Generator::new("6cbed15c793ce57650b9877cf6fa156fbef513c4e6134f022a85b1ffdd59b2a1")
-            .sign(nullifier_bytes);
+            .sign(0, nullifier_bytes);
 
     let packed_sig = pack_signature(&deposit_signature).unwrap();
 
@@ -204,28 +874,18 @@ fn verifiy_sig() {
 
     let signed_data =
         Generator::new("6cbed15c793ce57650b9877cf6fa156fbef513c4e6134f022a85b1ffdd59b2a1")
-            .sign(msg);
-
-    let r = signed_data.r;
-
-    let s = signed_data.s;
+            .sign(0, msg);
 
-    let v: u64 = signed_data.v.into();
-
-    let transport = web3::transports::Http::new("http://localhost:8545").unwrap();
-    let web3 = web3::Web3::new(transport);
-    let accounts = web3.accounts();
-
-    println!("r:{:#?}\n,s:{:#?},\nv:{:#?}", r, s, v);
+    println!(
+        "r:{},\ns:{},\nv:{}",
+        hex::encode(signed_data.r),
+        hex::encode(signed_data.s),
+        signed_data.v
+    );
 
     let packed_sig = pack_signature(&signed_data).unwrap();
     println!("packed : {:#?}", packed_sig);
-    let result = accounts.recover(web3::types::Recovery {
-        message: web3::types::RecoveryMessage::Data(msg.to_vec()),
-        v,
-        r,
-        s,
-    });
+    let result = signing::recover(msg, &signed_data);
 
     assert_eq!(packed_sig,"0xf70f2aa887c1f146e14a2fe5581805c6f93f99396e4f738740cf45a7af21d54c62ff74ee8b0712c0fe9bd0f94e71b9e2ccde83e7f1dff6c6f91c12a556eb014d");
 
@@ -235,5 +895,159 @@ fn verifiy_sig() {
 
     assert!(result.is_ok());
 
-    assert_eq!(result.unwrap(), web3::types::H160::from(client_address));
+    assert_eq!(result.unwrap(), client_address);
+}
+
+#[test]
+fn transfer_outputs_limit_test() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    let result = rt.block_on(
+        Generator::new("6cbed15c793ce57650b9877cf6fa156fbef513c4e6134f022a85b1ffdd59b2a1")
+            .generate_transfer(0, constants::OUT),
+    );
+
+    assert!(matches!(result, Err(TestError::GeneratorError(_))));
+}
+
+#[test]
+fn tx_kind_test() {
+    assert_eq!("deposit".parse::<TxKind>().unwrap().code(), "0000");
+    assert_eq!("transfer".parse::<TxKind>().unwrap().code(), "0001");
+    assert_eq!("withdrawal".parse::<TxKind>().unwrap().code(), "0002");
+    assert_eq!("withdrawal".parse::<TxKind>().unwrap().name(), "withdrawal");
+    assert!("swap".parse::<TxKind>().is_err());
+}
+
+#[test]
+fn receiver_address_test() {
+    let receiver =
+        WithdrawalParams::parse_receiver("0xFFcf8FDEE72ac11b5c542428B35EEF5769C409f0").unwrap();
+
+    assert_eq!(
+        receiver,
+        Generator::new("6cbed15c793ce57650b9877cf6fa156fbef513c4e6134f022a85b1ffdd59b2a1")
+            .address()
+    );
+    assert!(WithdrawalParams::parse_receiver("0xFFcf8FDE").is_err());
+}
+
+#[test]
+fn tx_amounts_test() {
+    assert_eq!("5".parse::<AmountRange>().unwrap(), AmountRange::fixed(5));
+    assert_eq!(
+        "1000..3000".parse::<AmountRange>().unwrap(),
+        AmountRange {
+            min: 1000,
+            max: 3000
+        }
+    );
+    assert!("3000..1000".parse::<AmountRange>().is_err());
+    assert!("1000..".parse::<AmountRange>().is_err());
+
+    let amounts = TxAmounts::new(
+        "1000..3000".parse().unwrap(),
+        AmountRange::fixed(1000),
+        1000,
+    )
+    .unwrap();
+    let mut rng = rand::thread_rng();
+    let mut counts = std::collections::BTreeMap::new();
+    for _ in 0..3000 {
+        *counts.entry(amounts.sample_amount(&mut rng)).or_insert(0) += 1;
+        assert_eq!(amounts.sample_fee(&mut rng), 1);
+    }
+    assert_eq!(counts.keys().copied().collect::<Vec<u64>>(), [1, 2, 3]);
+    assert!(counts.values().all(|count| (800..1200).contains(count)), "{:?}", counts);
+
+    assert!(TxAmounts::new(AmountRange::fixed(1500), AmountRange::fixed(0), 1000).is_err());
+    assert!(TxAmounts::new(AmountRange::fixed(1000), AmountRange::fixed(0), 0).is_err());
+}
+
+#[test]
+fn proving_params_missing_test() {
+    let params = ProvingParams::new(
+        String::from("./missing/transfer_params.bin"),
+        String::from("./missing/transfer_verification_key.json"),
+    );
+
+    assert!(matches!(params.get(), Err(TestError::FileAccessError(_))));
+    // a failed load is retried rather than cached
+    assert!(matches!(params.get(), Err(TestError::FileAccessError(_))));
+}
+
+#[test]
+fn seeded_generation_test() {
+    let generator =
+        Generator::new("6cbed15c793ce57650b9877cf6fa156fbef513c4e6134f022a85b1ffdd59b2a1")
+            .with_amounts(
+                TxAmounts::new("1000..2000".parse().unwrap(), "0..1000".parse().unwrap(), 1)
+                    .unwrap(),
+            )
+            .with_seed(42);
+    let replay = generator.clone().with_seed(42);
+
+    let first = generator.create_deposit(7).unwrap();
+    let second = replay.create_deposit(7).unwrap();
+    let other = generator.create_deposit(8).unwrap();
+    assert_eq!((first.amount, first.fee), (second.amount, second.fee));
+
+    let (first, second, other) = (first.tx_data, second.tx_data, other.tx_data);
+
+    // notes, memo ciphertext and witness alike
+    let bytes = |tx: &TransactionData<Fr>| {
+        serde_json::to_vec(&(&tx.public, &tx.secret, &tx.memo, &tx.out_hashes)).unwrap()
+    };
+    assert_eq!(bytes(&first), bytes(&second));
+    assert_ne!(first.public.nullifier, other.public.nullifier);
+    assert_ne!(first.ciphertext, other.ciphertext);
+}
+
+#[test]
+fn withdrawal_native_amount_test() {
+    let generator =
+        Generator::new("6cbed15c793ce57650b9877cf6fa156fbef513c4e6134f022a85b1ffdd59b2a1")
+            .with_amounts(
+                TxAmounts::new(AmountRange::fixed(1000), AmountRange::fixed(0), 1000).unwrap(),
+            );
+    let withdrawal = |native_amount| WithdrawalParams {
+        receiver: generator.address(),
+        native_amount,
+        energy: 0,
+    };
+
+    assert!(generator.create_withdrawal(0, &withdrawal(0)).is_ok());
+    assert!(matches!(
+        generator.create_withdrawal(0, &withdrawal(1000)),
+        Err(TestError::GeneratorError(_))
+    ));
+}
+
+#[test]
+fn key_assignment_test() {
+    let keys = crate::depositors::from_mnemonic(
+        "myth like bonus scare over problem client lizard pioneer submit female collect",
+        3,
+    )
+    .unwrap();
+    let generator =
+        Generator::new("6cbed15c793ce57650b9877cf6fa156fbef513c4e6134f022a85b1ffdd59b2a1");
+
+    let round_robin = generator
+        .clone()
+        .with_keys(keys.clone(), KeyAssignment::RoundRobin)
+        .unwrap();
+    assert_eq!(round_robin.key(4), &keys[1]);
+
+    let random = generator
+        .clone()
+        .with_keys(keys.clone(), KeyAssignment::Random)
+        .unwrap()
+        .with_seed(1);
+    assert_eq!(random.key(4), random.clone().key(4));
+    assert!((0..30).any(|index| random.key(index) != random.key(0)));
+
+    assert!(generator.with_keys(vec![], KeyAssignment::RoundRobin).is_err());
 }
@@ -0,0 +1,140 @@
+//! Latency distributions. Samples go into HdrHistograms with microsecond resolution, so long
+//! runs keep a fixed memory footprint and percentiles stay within 0.1% of the recorded value.
+
+use std::time::SystemTime;
+
+use hdrhistogram::Histogram;
+use serde::Serialize;
+
+/// Highest latency tracked, longer samples are clamped to it
+const MAX_MICROS: u64 = 3600 * 1_000_000;
+
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    histogram: Histogram<u64>,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            histogram: Histogram::new_with_bounds(1, MAX_MICROS, 3)
+                .expect("histogram bounds are valid"),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    /// Records a latency in seconds
+    pub fn record(&mut self, latency: f64) {
+        let micros = (latency * 1_000_000.0).round() as u64;
+        self.histogram
+            .saturating_record(micros.clamp(1, MAX_MICROS));
+    }
+
+    pub fn len(&self) -> u64 {
+        self.histogram.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.histogram.is_empty()
+    }
+
+    /// Latency in seconds at quantile `q` of `0.0..=1.0`
+    pub fn quantile(&self, q: f64) -> f64 {
+        self.histogram.value_at_quantile(q) as f64 / 1_000_000.0
+    }
+
+    pub fn quantiles(&self) -> Quantiles {
+        Quantiles {
+            count: self.len(),
+            p50: self.quantile(0.5),
+            p90: self.quantile(0.9),
+            p99: self.quantile(0.99),
+            max: self.histogram.max() as f64 / 1_000_000.0,
+        }
+    }
+
+    /// `count`, `p50`, `p90`, `p99` and `max` in seconds
+    pub fn summary(&self) -> String {
+        if self.is_empty() {
+            return String::from("count 0");
+        }
+
+        let quantiles = self.quantiles();
+        format!(
+            "count {}, p50 {:.3}s, p90 {:.3}s, p99 {:.3}s, max {:.3}s",
+            quantiles.count, quantiles.p50, quantiles.p90, quantiles.p99, quantiles.max
+        )
+    }
+}
+
+/// Latency quantiles in seconds, all zero without samples
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Quantiles {
+    pub count: u64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// Raw latencies, measured from the actual start of each request, next to latencies corrected
+/// for coordinated omission, measured from the time the request was scheduled. When the sender
+/// falls behind its schedule the raw distribution leaves out the time requests spent waiting
+/// to go out, the corrected one doesn't.
+#[derive(Debug, Clone, Default)]
+pub struct Latencies {
+    pub raw: LatencyHistogram,
+    pub corrected: LatencyHistogram,
+}
+
+impl Latencies {
+    /// Records a request started at `started`, scheduled at `scheduled` if it was paced, that
+    /// ended at `finished`
+    pub fn record(
+        &mut self,
+        scheduled: Option<SystemTime>,
+        started: SystemTime,
+        finished: SystemTime,
+    ) {
+        let since = |start: SystemTime| {
+            finished
+                .duration_since(start)
+                .map_or(0.0, |latency| latency.as_secs_f64())
+        };
+
+        self.raw.record(since(started));
+        // a request going out early isn't faster than it was
+        let scheduled = scheduled.map_or(started, |scheduled| scheduled.min(started));
+        self.corrected.record(since(scheduled));
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "raw {}; corrected {}",
+            self.raw.summary(),
+            self.corrected.summary()
+        )
+    }
+}
+
+#[test]
+fn corrected_latencies_test() {
+    use std::time::Duration;
+
+    let at = |millis: u64| SystemTime::UNIX_EPOCH + Duration::from_millis(millis);
+
+    let mut latencies = Latencies::default();
+    // on schedule
+    latencies.record(Some(at(0)), at(0), at(100));
+    // went out 900ms late because the sender stalled
+    latencies.record(Some(at(100)), at(1000), at(1100));
+    // not paced
+    latencies.record(None, at(2000), at(2100));
+
+    assert_eq!(latencies.raw.len(), 3);
+    assert!((latencies.raw.quantile(1.0) - 0.1).abs() < 0.001);
+    assert!((latencies.corrected.quantile(0.5) - 0.1).abs() < 0.001);
+    assert!((latencies.corrected.quantile(1.0) - 1.0).abs() < 0.001);
+    assert_eq!(LatencyHistogram::default().summary(), "count 0");
+}
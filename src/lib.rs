@@ -1,4 +1,21 @@
 pub mod telemetry;
 pub mod sender;
 pub mod generator;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod chain;
+pub mod signing;
+pub mod sync;
+pub mod depositors;
+pub mod corpus;
+pub mod profile;
+pub mod tracker;
+pub mod latency;
+pub mod retry;
+pub mod client;
+pub mod scenario;
+pub mod mix;
+pub mod pipeline;
+pub mod prover;
+pub mod seeded;
+#[cfg(test)]
+mod test_relayer;
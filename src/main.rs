@@ -1,42 +1,197 @@
 use futures_util::stream::FuturesUnordered;
 use load_runner::{
-    generator::{Deposit, Generator},
-    sender::{send_tx, JobResult, JobStatus},
+    chain::{AccountChain, ChainParams},
+    client::{Header, HttpConfig, Relayer},
+    corpus::{self, ManifestEntry, ManifestWriter},
+    depositors::{self, KeyAssignment},
+    generator::{AmountRange, Generator, ProvedTx, TxAmounts, TxKind, WithdrawalParams},
+    mix::TxMix,
+    pipeline::{self, PipelineStats, QueueReceiver, Schedule},
+    prover::ProverPool,
+    profile::LoadProfile,
+    retry::{RetryPolicy, Retryable},
+    scenario::{Outputs, RunSummary, Scenario, TypeSummary},
+    tracker::{JobTracker, StageLatencies, TrackedJob},
+    sender::{
+        run_virtual_user, send_tx, JobResult, JobStatus, PendingTx, SendStats, SubmitResult,
+    },
+    sync::PoolSync,
     telemetry::*,
     utils::TestError,
 };
-use tokio::{runtime::Runtime, sync::mpsc};
+use tokio::{
+    runtime::Runtime,
+    sync::mpsc,
+    task::JoinHandle,
+    time::{self, Instant},
+};
 
 use std::{
+    collections::{BTreeMap, VecDeque},
     env, fs,
-    io::Write,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::Arc,
     sync::atomic::{AtomicUsize, Ordering},
     thread,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use lazy_static::lazy_static;
+use rand::{rngs::StdRng, SeedableRng};
 use prometheus::{labels, register_counter, register_histogram, Counter, Histogram};
 
 use futures::prelude::*;
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
     #[clap(short, long, default_value = "deposit")]
     tx_type: String,
+    /// Number of output notes in generated transfers
+    #[clap(long, default_value = "1")]
+    outputs: u8,
+    /// Withdrawal receiver address, defaults to the depositor address
+    #[clap(long)]
+    receiver: Option<String>,
+    /// Token amount swapped to native coins on withdrawal
+    #[clap(long, default_value = "0")]
+    native_amount: u64,
+    /// Energy withdrawn with the withdrawal
+    #[clap(long, default_value = "0")]
+    energy: u64,
+    /// Token amount deposited, either fixed `N` or a random `MIN..MAX` range per tx
+    #[clap(long, default_value = "1000000000")]
+    amount: AmountRange,
+    /// Token fee paid by every transaction, either fixed `N` or a random `MIN..MAX` range
+    #[clap(long, default_value = "0")]
+    fee: AmountRange,
+    /// Pool token denominator, amounts and fees have to be its multiples
+    #[clap(long, default_value = "1000000000")]
+    denominator: u64,
+    /// Build generated transactions on top of the relayer pool state
+    #[clap(long)]
+    sync: bool,
+    /// Seconds to wait for a relayer job to be mined
+    #[clap(long, default_value = "120")]
+    job_timeout: u64,
+    /// Seed for the generated accounts, amounts and fees, random if not set. In send mode
+    /// only transactions generated with this seed are sent.
+    #[clap(long)]
+    seed: Option<u64>,
+    /// Only send transactions of this type from the corpus manifest
+    #[clap(long)]
+    filter_type: Option<TxKind>,
+    /// Send a weighted mix of tx types drawn from the corpus manifest, e.g.
+    /// `transfer:60,deposit:30,withdrawal:10`. With `seed` the draw is reproducible.
+    #[clap(long, conflicts_with = "filter-type")]
+    mix: Option<TxMix>,
+    /// Send arrival rate in txs/sec: `constant:RATE`, `ramp:FROM:TO:SECS`,
+    /// `step:START:INCREMENT:SECS` or `spike:BASE:PEAK:AT:SECS`. Defaults to `threads` txs/sec.
+    #[clap(long)]
+    profile: Option<LoadProfile>,
+    /// Send with this many closed-model virtual users instead of an arrival-rate profile
+    #[clap(long, conflicts_with = "profile")]
+    users: Option<u16>,
+    /// Seconds after which send stops waiting for in-flight txs and reports what is left
+    #[clap(long)]
+    deadline: Option<u64>,
+    /// Attempts per relayer request, 1 sends every request once
+    #[clap(long, default_value = "1")]
+    max_attempts: u32,
+    /// Milliseconds before the first retry, doubled for every further one and jittered
+    #[clap(long, default_value = "200")]
+    retry_backoff: u64,
+    /// Upper bound of the retry backoff in milliseconds
+    #[clap(long, default_value = "10000")]
+    retry_max_backoff: u64,
+    /// Comma separated failures worth a retry: HTTP statuses and `timeout`, `connect`,
+    /// `invalid-proof`, `duplicate-nullifier`, `insufficient-balance`, `http`, `bad-response`
+    #[clap(
+        long,
+        use_value_delimiter = true,
+        default_value = "timeout,connect,429,500,502,503,504"
+    )]
+    retry_on: Vec<Retryable>,
+    /// Relayer request timeout in milliseconds, response body included
+    #[clap(long, default_value = "5000")]
+    request_timeout: u64,
+    /// Relayer connect timeout in milliseconds
+    #[clap(long)]
+    connect_timeout: Option<u64>,
+    /// Idle relayer connections kept open, unlimited by default
+    #[clap(long)]
+    pool_max_idle: Option<usize>,
+    /// Seconds an idle relayer connection is kept open
+    #[clap(long, default_value = "90")]
+    pool_idle_timeout: u64,
+    /// TCP keep-alive interval of relayer connections in seconds
+    #[clap(long)]
+    tcp_keepalive: Option<u64>,
+    /// Talk HTTP/2 to the relayer without negotiating it first
+    #[clap(long)]
+    http2: bool,
+    /// Proxy URL for every relayer request
+    #[clap(long)]
+    proxy: Option<String>,
+    /// `NAME: VALUE` header added to every relayer request, can be repeated
+    #[clap(long = "header", multiple_occurrences = true)]
+    headers: Vec<Header>,
+    /// Milliseconds between two polls of the submitted relayer jobs
+    #[clap(long, default_value = "500")]
+    poll_interval: u64,
+    /// Relayer jobs polled at a time
+    #[clap(long, default_value = "32")]
+    poll_concurrency: usize,
+    /// Number of depositor keys derived from `MNEMONIC`
+    #[clap(long, default_value = "10")]
+    keys: u32,
+    /// How depositor keys are assigned to deposits: `round-robin` or `random`
+    #[clap(long, default_value = "round-robin")]
+    key_assignment: KeyAssignment,
     #[clap(short, long, default_value = "1")]
-    count: u16,
+    count: u32,
+    /// Async runtime threads, proving runs on its own `provers` threads. Also the number of
+    /// chains run at a time in chain mode.
     #[clap(long, default_value = "1")]
     threads: u8,
-    #[clap(short, long)]
-    mode: String,
+    /// `generate`, `send`, `publish`, `chain`, or `run` to prove txs and send them as soon as
+    /// they are ready
+    #[clap(short, long, required = true)]
+    mode: Option<String>,
+    /// In run mode, also save the proved txs and their manifest to `TX_FOLDER`
+    #[clap(long)]
+    persist: bool,
+    /// Proving threads of generate, chain and run modes, 2 by default as every proof is
+    /// multicore already
+    #[clap(long)]
+    provers: Option<usize>,
+    /// Proved txs kept ready for the sender in run mode, provers wait once it is full
+    #[clap(long, default_value = "100")]
+    queue_size: usize,
+    /// Push the relayer processing times of finished jobs to the Prometheus push gateway once
+    /// send or run mode is done
+    #[clap(long)]
+    publish: bool,
     #[clap(long, default_value = "0")]
     skip: u8,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Runs a scenario file end to end: generates or picks its txs, sends them and checks
+    /// its thresholds
+    Run { scenario: String },
+}
+
 const DEFAULT_SK: &str = "6cbed15c793ce57650b9877cf6fa156fbef513c4e6134f022a85b1ffdd59b2a1";
 const DEFAULT_RELAYER_URL: &str = "http://localhost:8000";
+/// Corpus txs read ahead of the sender
+const TX_BUFFER: usize = 1000;
+/// Seconds between two progress reports of a send
+const REPORT_INTERVAL: u64 = 10;
 
 // #[tokio::main]
 
@@ -73,52 +228,871 @@ lazy_static! {
     .unwrap();
 }
 
-fn send(threads: usize, rt: &Runtime, limit: usize, skip: usize) -> Result<(), TestError> {
-    let txs_folder = env::var("TX_FOLDER").unwrap_or("./txs".to_owned());
-    let txs = fs::read_dir(txs_folder).unwrap();
+fn retry_policy(args: &Args) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: args.max_attempts.max(1),
+        backoff: Duration::from_millis(args.retry_backoff),
+        max_backoff: Duration::from_millis(args.retry_max_backoff),
+        retry_on: args.retry_on.clone(),
+    }
+}
+
+/// Relayer client shared by every task, the `Authorization` header comes from `RELAYER_AUTH`
+fn relayer(args: &Args) -> Result<Relayer, TestError> {
+    let config = HttpConfig {
+        timeout: Duration::from_millis(args.request_timeout),
+        connect_timeout: args.connect_timeout.map(Duration::from_millis),
+        pool_max_idle: args.pool_max_idle,
+        pool_idle_timeout: Duration::from_secs(args.pool_idle_timeout),
+        tcp_keepalive: args.tcp_keepalive.map(Duration::from_secs),
+        http2: args.http2,
+        proxy: args.proxy.clone(),
+        headers: args.headers.clone(),
+        authorization: env::var("RELAYER_AUTH").ok(),
+    };
 
-    let (channel_sender, mut rx) = mpsc::channel::<JobResult>(1000);
-    // let count = args.count.into();
-    for (index, entry) in txs.enumerate() {
-        if index < skip {
-            continue;
+    Ok(Relayer {
+        client: config.build()?,
+        url: env::var("RELAYER_URL").unwrap_or(DEFAULT_RELAYER_URL.to_owned()),
+        retry: retry_policy(args),
+    })
+}
+
+/// Load, deadline and outputs of send and run modes
+fn send_options(args: &Args) -> SendOptions {
+    let load = match (args.users, args.profile.clone()) {
+        (Some(users), _) => Load::Closed {
+            users: users.into(),
+            job_timeout: Duration::from_secs(args.job_timeout),
+        },
+        (None, Some(profile)) => Load::Open(profile),
+        // the legacy pacing sent `threads` txs every second
+        (None, None) => Load::Open(LoadProfile::Constant {
+            rate: args.threads.into(),
+        }),
+    };
+
+    SendOptions {
+        load,
+        deadline: args.deadline.map(Duration::from_secs),
+        outputs: Outputs::default(),
+        publish: args.publish,
+    }
+}
+
+fn job_tracker(args: &Args, relayer: &Relayer) -> JobTracker {
+    JobTracker::new(
+        relayer.client.clone(),
+        relayer.url.clone(),
+        Duration::from_millis(args.poll_interval),
+        Duration::from_secs(args.job_timeout),
+    )
+    .with_retry(relayer.retry.clone())
+    .with_concurrency(args.poll_concurrency)
+}
+
+/// Withdrawals go to `--receiver`, or back to the depositor address
+fn withdrawal_params(args: &Args, generator: &Generator) -> Result<WithdrawalParams, TestError> {
+    Ok(WithdrawalParams {
+        receiver: match &args.receiver {
+            Some(receiver) => WithdrawalParams::parse_receiver(receiver)?,
+            None => generator.address(),
+        },
+        native_amount: args.native_amount,
+        energy: args.energy,
+    })
+}
+
+/// Depositor keys from `KEY_FILE` or `MNEMONIC`, `None` means the single `SK` key is used
+fn depositor_keys(count: u32) -> Result<Option<Vec<secp256k1::SecretKey>>, TestError> {
+    match (env::var("KEY_FILE"), env::var("MNEMONIC")) {
+        (Ok(_), Ok(_)) => Err(TestError::ConfigError(String::from(
+            "KEY_FILE and MNEMONIC are both set, keep one of them",
+        ))),
+        (Ok(path), _) => depositors::from_file(&path).map(Some),
+        (_, Ok(mnemonic)) => depositors::from_mnemonic(&mnemonic, count).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Files of a corpus in sending order: the manifest order when the corpus has one, the
+/// directory order otherwise
+fn corpus_files(
+    folder: &str,
+    tx_type: Option<TxKind>,
+    seed: Option<u64>,
+) -> Result<Vec<String>, TestError> {
+    match corpus::read(folder)? {
+        Some(entries) => Ok(corpus::select(entries, tx_type.map(|kind| kind.name()), seed)
+            .into_iter()
+            .map(|entry| entry.file_name)
+            .collect()),
+        None => {
+            tracing::warn!("no manifest in {}, sending files in directory order", folder);
+            if tx_type.is_some() || seed.is_some() {
+                return Err(TestError::ConfigError(String::from(
+                    "filtering a corpus requires its manifest",
+                )));
+            }
+            fs::read_dir(folder)?
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect()
         }
-        if index == limit + skip {
-            break;
+    }
+}
+
+/// `count` files drawn from the per-type pools of a corpus in `mix` proportions
+fn mix_files(
+    folder: &str,
+    mix: &TxMix,
+    seed: Option<u64>,
+    count: usize,
+) -> Result<Vec<String>, TestError> {
+    let pools = mix
+        .kinds()
+        .map(|kind| Ok((kind, corpus_files(folder, Some(kind), seed)?.into())))
+        .collect::<Result<BTreeMap<_, _>, TestError>>()?;
+
+    Ok(mix.draw(pools, count, &mut mix_rng(seed)))
+}
+
+/// Draws of a mix are reproducible for a seeded corpus
+fn mix_rng(seed: Option<u64>) -> StdRng {
+    seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64)
+}
+
+/// How `send` drives the relayer
+#[derive(Debug)]
+enum Load {
+    /// Fire-and-forget sends following an arrival-rate profile
+    Open(LoadProfile),
+    /// Virtual users each waiting for their job to finish before sending the next tx
+    Closed { users: usize, job_timeout: Duration },
+}
+
+#[derive(Debug)]
+struct SendOptions {
+    load: Load,
+    /// Sending stops and in-flight txs are given up after this long
+    deadline: Option<Duration>,
+    outputs: Outputs,
+    /// Push the relayer processing times of finished jobs to the Prometheus push gateway
+    publish: bool,
+}
+
+/// Txs streamed to `send` in sending order by a producer task
+struct TxStream {
+    /// Txs the producer is going to stream, unless it fails
+    total: usize,
+    rx: QueueReceiver<PendingTx<serde_json::Value>>,
+    producer: JoinHandle<Result<(), TestError>>,
+}
+
+/// Streams corpus files to the sender, read ahead of their send time so disk access doesn't
+/// delay sends
+fn read_txs(rt: &Runtime, txs_folder: String, txs: Vec<String>) -> TxStream {
+    let total = txs.len();
+    let (sender, rx) = pipeline::queue(TX_BUFFER, Arc::default());
+    let producer = rt.spawn(async move {
+        for file_name in txs {
+            let content = fs::read(Path::new(&txs_folder).join(&file_name))?;
+            let tx: serde_json::Value =
+                serde_json::from_slice(&content).map_err(TestError::SerializationError)?;
+            // the type comes from the payload, so corpora without a manifest are tagged too
+            let tx_type = tx["txType"].as_str().and_then(TxKind::from_code);
+            let pending = PendingTx {
+                file_name,
+                tx_type,
+                tx,
+            };
+            if !sender.send(pending).await {
+                break;
+            }
         }
+        Ok(())
+    });
+
+    TxStream {
+        total,
+        rx,
+        producer,
+    }
+}
+
+/// Prover side of a run
+#[derive(Debug, Clone)]
+struct ProverOptions {
+    pool: ProverPool,
+    /// Proved txs kept ready for the sender
+    queue_size: usize,
+}
+
+/// The `index`-th tx of a run, proved by `generator`
+struct ProofJob {
+    generator: Generator,
+    kind: TxKind,
+    index: u64,
+    outputs: usize,
+    withdrawal: WithdrawalParams,
+}
 
-        if index % threads == 0 {
-            thread::sleep(Duration::from_millis(1000));
+impl ProofJob {
+    fn prove(&self) -> Result<ProvedTx, TestError> {
+        self.generator
+            .prove(self.kind, self.index, self.outputs, &self.withdrawal)
+    }
+}
+
+/// Proves the `total` txs of `jobs` and streams them to the sender through a bounded queue
+/// as soon as they are ready. With a `manifest` they are saved to the corpus as well.
+fn prove_txs<I>(
+    rt: &Runtime,
+    jobs: I,
+    total: usize,
+    provers: ProverOptions,
+    mut manifest: Option<ManifestWriter>,
+) -> TxStream
+where
+    I: Iterator<Item = ProofJob> + Send + 'static,
+{
+    let persist = manifest.is_some();
+    let ProverOptions { pool, queue_size } = provers;
+    let (sender, rx) = pipeline::queue(queue_size, Arc::default());
+    let producer = rt.spawn(async move {
+        let workers = pool.size();
+        let mut proved = stream::iter(jobs)
+            .map(|job| {
+                pool.run(move || {
+                    let started = std::time::Instant::now();
+                    let proved = job.prove()?;
+                    let proof_time = started.elapsed();
+                    if persist {
+                        job.generator.save(&proved)?;
+                    }
+                    Ok((proved, proof_time))
+                })
+            })
+            .buffer_unordered(workers);
+
+        let stats = sender.stats().clone();
+        while let Some(result) = proved.next().await {
+            let proved = match result {
+                Ok((proved, proof_time)) => {
+                    stats.record_proof(proof_time);
+                    proved
+                }
+                Err(e) => {
+                    stats.failed.fetch_add(1, Ordering::Relaxed);
+                    tracing::error!("failed to prove tx: {:?}", e);
+                    continue;
+                }
+            };
+            if let Some(manifest) = &mut manifest {
+                manifest.write(&proved.entry)?;
+            }
+
+            let pending = PendingTx {
+                file_name: proved.entry.file_name,
+                tx_type: Some(proved.kind),
+                tx: proved.payload,
+            };
+            // the sender stops taking txs at the deadline
+            if !sender.send(pending).await {
+                break;
+            }
         }
+        Ok(())
+    });
 
-        let tx = entry.unwrap();
-        let content = fs::read(tx.path().as_os_str()).unwrap();
-        let d: Deposit = serde_json::from_slice::<Deposit>(&content).unwrap();
-        let file_name = tx.file_name().to_string_lossy().into_owned();
-        let mpsc_sender = channel_sender.clone();
-        let relayer_url = env::var("RELAYER_URL").unwrap_or(DEFAULT_RELAYER_URL.to_owned());
-        rt.spawn(async {
-            send_tx(file_name, d, mpsc_sender, relayer_url).await;
-        });
+    TxStream {
+        total,
+        rx,
+        producer,
     }
+}
+
+fn send(
+    rt: &Runtime,
+    txs: TxStream,
+    options: &SendOptions,
+    tracker: JobTracker,
+    relayer: &Relayer,
+) -> Result<RunSummary, TestError> {
+    let TxStream {
+        total,
+        rx: pending,
+        producer,
+    } = txs;
+
+    let (channel_sender, mut rx) = mpsc::channel::<SubmitResult>(1000);
+    let stats = Arc::new(SendStats::default());
+    let pipeline = pending.stats().clone();
+    let start = Instant::now();
+    let started = SystemTime::now();
+    let deadline = options.deadline.map(|deadline| start + deadline);
 
-    let _rx_handle = rt.spawn(async move {
-        let mut file = fs::OpenOptions::new()
+    let (tracker_sender, tracker_rx) = mpsc::channel::<JobResult>(1000);
+    let (finished_sender, mut finished_rx) = mpsc::channel::<TrackedJob>(1000);
+    let tracker = rt.spawn(tracker.run(tracker_rx, finished_sender, deadline));
+
+    let mut file = BufWriter::new(
+        fs::OpenOptions::new()
             .append(true)
-            .open("result.log")
-            .unwrap();
+            .create(true)
+            .open(&options.outputs.result_log)?,
+    );
+    let rx_handle = rt.spawn(async move {
         // Start receiving messages
-        while let Some(job_result) = rx.recv().await {
-            let content = serde_json::to_string(&job_result).unwrap();
-            tracing::info!("received job result {}", content);
+        while let Some(result) = rx.recv().await {
+            let content = serde_json::to_string(&result).unwrap();
+            tracing::info!("received submit result {}", content);
             if let Err(e) = writeln!(file, "{}", content) {
                 eprintln!("Couldn't write to file: {}", e);
             }
+            if let SubmitResult::Job(job_result) = result {
+                if tracker_sender.send(job_result).await.is_err() {
+                    tracing::error!("job tracker stopped");
+                }
+            }
         }
+        file.flush()
     });
 
-    thread::sleep(Duration::from_millis(10000));
-    Ok(())
+    let mut jobs_file = BufWriter::new(
+        fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&options.outputs.jobs_log)?,
+    );
+    let finished_handle = rt.spawn(async move {
+        let mut states: BTreeMap<String, usize> = BTreeMap::new();
+        let mut latencies = StageLatencies::default();
+        let mut relayer_elapsed = vec![];
+        while let Some(job) = finished_rx.recv().await {
+            let state = job.state().map_or("unpolled", |state| state.name());
+            match job.latency {
+                Some(latency) => tracing::info!("job {} {} in {:.3}s", job.job_id, state, latency),
+                None => tracing::warn!("job {} still {}", job.job_id, state),
+            }
+            match serde_json::to_string(&job) {
+                Ok(content) => writeln!(jobs_file, "{}", content)?,
+                Err(e) => tracing::error!("can't serialize job {}: {}", job.job_id, e),
+            }
+            *states.entry(state.to_owned()).or_default() += 1;
+            latencies.record(&job);
+            if job.latency.is_some() {
+                relayer_elapsed.push(f64::from(job.relayer_elapsed) / 1000.0);
+            }
+        }
+        jobs_file.flush()?;
+        Ok::<_, std::io::Error>((states, latencies, relayer_elapsed))
+    });
+
+    let monitor = rt.spawn(monitor(pipeline.clone(), stats.clone(), start));
+    let mut tasks = FuturesUnordered::new();
+
+    match &options.load {
+        Load::Open(profile) => {
+            tracing::info!("sending {} txs with {:?}", total, profile);
+
+            let mut pending = pending;
+            let mut schedule = Schedule::default();
+            rt.block_on(async {
+                for offset in profile.arrivals().take(total) {
+                    // take the tx before sleeping, so reading or proving it doesn't delay the send
+                    let due = start + schedule.due(offset);
+                    let asked = Instant::now();
+                    let next = match deadline {
+                        Some(deadline) if due > deadline => break,
+                        Some(deadline) => time::timeout_at(deadline, pending.take(due))
+                            .await
+                            .ok()
+                            .flatten(),
+                        None => pending.take(due).await,
+                    };
+                    let next = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    // waiting past the due time for the producer counts as starvation, it is
+                    // left out of the corrected latencies of the relayer
+                    schedule.taken(asked, due, Instant::now());
+                    let offset = schedule.due(offset);
+
+                    time::sleep_until(start + offset).await;
+
+                    tasks.push(tokio::spawn(send_tx(
+                        next,
+                        Some(started + offset),
+                        channel_sender.clone(),
+                        relayer.clone(),
+                        stats.clone(),
+                    )));
+                }
+            });
+        }
+        Load::Closed { users, job_timeout } => {
+            tracing::info!("sending {} txs with {} virtual users", total, users);
+
+            let shared = Arc::new(tokio::sync::Mutex::new(pending));
+            tasks.extend((0..*users).map(|user| {
+                rt.spawn(run_virtual_user(
+                    user,
+                    shared.clone(),
+                    channel_sender.clone(),
+                    relayer.clone(),
+                    *job_timeout,
+                    stats.clone(),
+                ))
+            }));
+        }
+    }
+
+    rt.block_on(wait_for_tasks(&mut tasks, deadline));
+    tasks.iter().for_each(|task| task.abort());
+    drop(tasks);
+    // submissions without an answer, a virtual user polling its job has none outstanding
+    let in_flight = stats.sent.load(Ordering::Relaxed).saturating_sub(
+        stats.submitted.load(Ordering::Relaxed) + stats.rejected.load(Ordering::Relaxed),
+    );
+
+    // a producer still running was cut off by the deadline
+    producer.abort();
+    let produced = match rt.block_on(producer) {
+        Ok(produced) => produced,
+        Err(e) if e.is_cancelled() => Ok(()),
+        Err(e) => Err(TestError::GeneratorError(format!("tx producer failed: {}", e))),
+    };
+    monitor.abort();
+    let sent = stats.sent.load(Ordering::Relaxed);
+
+    drop(channel_sender);
+    rt.block_on(rx_handle).map_err(|_| TestError::MpscError)??;
+
+    if let Err(e) = rt.block_on(tracker) {
+        tracing::error!("job tracker failed: {}", e);
+    }
+    let (states, latencies, relayer_elapsed) = rt
+        .block_on(finished_handle)
+        .map_err(|_| TestError::MpscError)??;
+    // the logs of what was sent are complete, but the run is cut short
+    if let Err(e) = produced {
+        tracing::error!(
+            "producing txs failed after {} sent: {}",
+            stats.sent.load(Ordering::Relaxed),
+            e
+        );
+        return Err(e);
+    }
+    tracing::info!(
+        "sent {} of {} txs in {:?}: {} submitted, {} rejected, {} in flight at the deadline, \
+         jobs {:?}",
+        sent,
+        total,
+        start.elapsed(),
+        stats.submitted.load(Ordering::Relaxed),
+        stats.rejected.load(Ordering::Relaxed),
+        in_flight,
+        states
+    );
+    let errors = stats.errors.lock().unwrap();
+    for (kind, count) in errors.iter() {
+        tracing::info!(
+            "rejected {}: {} ({:.1}% of sent)",
+            kind.name(),
+            count,
+            100.0 * *count as f64 / sent as f64
+        );
+    }
+    for (status, samples) in stats.http_latencies.lock().unwrap().iter() {
+        let status = status.map_or(String::from("no response"), |status| status.to_string());
+        tracing::info!("POST /transaction {}: {}", status, samples.summary());
+    }
+    tracing::info!("jobs: {}", latencies.jobs.summary());
+    let by_type = stats.by_type.lock().unwrap();
+    for (kind, type_stats) in by_type.iter() {
+        tracing::info!(
+            "{}: {} submitted, {} rejected, POST /transaction {}",
+            kind.name(),
+            type_stats.submitted,
+            type_stats.rejected,
+            type_stats.http_latencies.summary()
+        );
+    }
+    for (kind, jobs) in &latencies.by_type {
+        tracing::info!("{} jobs: {}", kind.name(), jobs.summary());
+    }
+    for stage in latencies.report() {
+        tracing::info!("stage {}", stage);
+    }
+    let pipeline_summary = pipeline.summary(start.elapsed());
+    if pipeline_summary.proved > 0 || pipeline_summary.failed > 0 {
+        tracing::info!(
+            "proved {} txs ({} failed) at {:.2} txs/sec, proof time {}",
+            pipeline_summary.proved,
+            pipeline_summary.failed,
+            pipeline_summary.prover_rate,
+            pipeline.proof_times.lock().unwrap().summary()
+        );
+    }
+    tracing::info!(
+        "queue depth p50 {}, max {}; sender starved {}",
+        pipeline_summary.queue_depth_p50,
+        pipeline_summary.queue_depth_max,
+        pipeline.starvation.lock().unwrap().summary()
+    );
+    if options.publish {
+        let batch_size = env::var("BATCH_SIZE").map_or(1, |size| size.parse().unwrap_or(1));
+        publish(&PUSH_REQ_HISTOGRAM, &relayer_elapsed, batch_size.max(1));
+    }
+
+    // the run went on without them, but it didn't send what it was asked to
+    if pipeline_summary.failed > 0 {
+        return Err(TestError::GeneratorError(format!(
+            "{} txs failed to prove",
+            pipeline_summary.failed
+        )));
+    }
+
+    Ok(RunSummary {
+        total,
+        sent,
+        submitted: stats.submitted.load(Ordering::Relaxed),
+        rejected: stats.rejected.load(Ordering::Relaxed),
+        in_flight,
+        errors: errors
+            .iter()
+            .map(|(kind, count)| (kind.name().to_owned(), *count))
+            .collect(),
+        jobs: states,
+        elapsed: start.elapsed().as_secs_f64(),
+        latency: latencies.jobs.raw.quantiles(),
+        corrected_latency: latencies.jobs.corrected.quantiles(),
+        types: by_type
+            .iter()
+            .map(|(kind, type_stats)| {
+                let jobs = latencies.by_type.get(kind).cloned().unwrap_or_default();
+                let summary = TypeSummary {
+                    submitted: type_stats.submitted,
+                    rejected: type_stats.rejected,
+                    latency: jobs.raw.quantiles(),
+                    corrected_latency: jobs.corrected.quantiles(),
+                };
+                (kind.name().to_owned(), summary)
+            })
+            .collect(),
+        pipeline: pipeline_summary,
+    })
+}
+
+/// Samples the queue depth every second and logs the progress of a send every
+/// `REPORT_INTERVAL` seconds, until aborted
+async fn monitor(pipeline: Arc<PipelineStats>, stats: Arc<SendStats>, start: Instant) {
+    let mut interval = time::interval(Duration::from_secs(1));
+    let mut reported = start;
+    loop {
+        interval.tick().await;
+        pipeline.sample_depth();
+        if reported.elapsed() >= Duration::from_secs(REPORT_INTERVAL) {
+            reported = Instant::now();
+            tracing::info!(
+                "after {:?}: {} proved, {} queued, {} sent, sender starved {}",
+                start.elapsed(),
+                pipeline.proved.load(Ordering::Relaxed),
+                pipeline.depth(),
+                stats.sent.load(Ordering::Relaxed),
+                pipeline.starvation.lock().unwrap().summary()
+            );
+        }
+    }
+}
+
+/// Waits until every task is done or the deadline passes, finished tasks are removed
+async fn wait_for_tasks<T>(tasks: &mut FuturesUnordered<JoinHandle<T>>, deadline: Option<Instant>) {
+    loop {
+        let next = match deadline {
+            Some(deadline) => match time::timeout_at(deadline, tasks.next()).await {
+                Ok(next) => next,
+                Err(_) => return,
+            },
+            None => tasks.next().await,
+        };
+        match next {
+            Some(Err(e)) => tracing::error!("send task failed: {}", e),
+            Some(Ok(_)) => {}
+            None => return,
+        }
+    }
+}
+
+/// Proving threads, `size` or `DEFAULT_PROVERS`
+fn prover_pool(size: Option<usize>) -> ProverPool {
+    let pool = size.map_or_else(ProverPool::default, ProverPool::new);
+    tracing::info!("proving on {} threads", pool.size());
+    pool
+}
+
+/// Generates the txs of `jobs` into a corpus on the `prover` threads, returns the manifest
+/// entries of the saved ones. Fails once all jobs are done if any of them failed.
+fn generate(
+    rt: &Runtime,
+    prover: &ProverPool,
+    jobs: impl Iterator<Item = ProofJob>,
+    manifest: &mut ManifestWriter,
+) -> Result<Vec<ManifestEntry>, TestError> {
+    rt.block_on(async {
+        let mut completion_stream = jobs
+            .map(|job| {
+                prover.run(move || {
+                    let thread_name: String = thread::current().name().unwrap().to_owned();
+
+                    tracing::info!("{} started", thread_name);
+
+                    let proved = job.prove()?;
+                    job.generator.save(&proved)?;
+                    Ok(proved.entry)
+                })
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut entries = vec![];
+        let mut failed = 0;
+        while let Some(result) = completion_stream.next().await {
+            match result {
+                Ok(entry) => {
+                    tracing::info!("saved {} {}", entry.tx_type, entry.file_name);
+                    manifest.write(&entry)?;
+                    entries.push(entry);
+                }
+                Err(e) => {
+                    failed += 1;
+                    tracing::error!("failed to generate tx: {:?}", e);
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(TestError::GeneratorError(format!(
+                "{} of {} txs failed to generate",
+                failed,
+                failed + entries.len()
+            )));
+        }
+        Ok(entries)
+    })
+}
+
+/// Runs a scenario end to end, fails if one of its thresholds is breached
+fn run(scenario: Scenario) -> Result<(), TestError> {
+    tracing::info!("running scenario {}", scenario.name);
+    let rt = init_runtime(scenario.threads);
+
+    let settings = &scenario.relayer;
+    let relayer = Relayer {
+        client: settings
+            .http_config(env::var("RELAYER_AUTH").ok())?
+            .build()?,
+        url: settings
+            .url
+            .clone()
+            .or_else(|| env::var("RELAYER_URL").ok())
+            .unwrap_or(DEFAULT_RELAYER_URL.to_owned()),
+        retry: settings.retry.policy()?,
+    };
+
+    let corpus = &scenario.corpus;
+    let txs_folder = corpus
+        .folder
+        .clone()
+        .or_else(|| env::var("TX_FOLDER").ok())
+        .unwrap_or("./txs".to_owned());
+    let txs = if corpus.generate {
+        let manifest = match corpus.persist {
+            true => Some(ManifestWriter::open(&txs_folder)?),
+            false => None,
+        };
+        let jobs = scenario_jobs(&rt, &scenario, &relayer, &txs_folder)?;
+        let jobs = order_scenario(&scenario, jobs)?;
+        let total = jobs.len();
+        let provers = ProverOptions {
+            pool: prover_pool(corpus.provers),
+            queue_size: corpus.queue_size,
+        };
+        prove_txs(&rt, jobs.into_iter(), total, provers, manifest)
+    } else if scenario.txs.is_empty() {
+        read_txs(&rt, txs_folder.clone(), corpus_files(&txs_folder, None, corpus.seed)?)
+    } else {
+        let files = pick_scenario(&scenario, &txs_folder)?;
+        read_txs(&rt, txs_folder.clone(), order_scenario(&scenario, files)?)
+    };
+
+    let load = &scenario.load;
+    let job_timeout = Duration::from_secs(load.job_timeout_secs);
+    let options = SendOptions {
+        load: match load.profile()? {
+            Some(profile) => Load::Open(profile),
+            // without a profile the load has users
+            None => Load::Closed {
+                users: load.users.unwrap_or_default(),
+                job_timeout,
+            },
+        },
+        deadline: scenario.duration_secs.map(Duration::from_secs),
+        outputs: scenario.outputs.clone(),
+        publish: scenario.outputs.publish,
+    };
+    let tracker = JobTracker::new(
+        relayer.client.clone(),
+        relayer.url.clone(),
+        Duration::from_millis(load.poll_interval_ms),
+        job_timeout,
+    )
+    .with_retry(relayer.retry.clone())
+    .with_concurrency(load.poll_concurrency);
+
+    let summary = send(&rt, txs, &options, tracker, &relayer)?;
+    if let Some(path) = &scenario.outputs.summary {
+        let content =
+            serde_json::to_string_pretty(&summary).map_err(TestError::SerializationError)?;
+        fs::write(path, content)?;
+    }
+
+    let breaches = scenario.thresholds.check(&summary);
+    if breaches.is_empty() {
+        tracing::info!("scenario {} passed", scenario.name);
+        return Ok(());
+    }
+    for breach in &breaches {
+        tracing::error!("threshold breached: {}", breach);
+    }
+    Err(TestError::ThresholdError(breaches.join(", ")))
+}
+
+/// Generator signing deposits with `SK` or the `KEY_FILE`/`MNEMONIC` depositor keys, built on
+/// top of the relayer pool state if `sync` is set
+fn build_generator(
+    rt: &Runtime,
+    seed: Option<u64>,
+    keys: u32,
+    key_assignment: KeyAssignment,
+    sync: Option<&Relayer>,
+) -> Result<Generator, TestError> {
+    let sk = env::var("SK").unwrap_or(DEFAULT_SK.to_owned());
+    let mut generator = Generator::new(sk.as_str());
+    if let Some(seed) = seed {
+        generator = generator.with_seed(seed);
+    }
+    tracing::info!("generating with seed {}", generator.seed());
+    if let Some(keys) = depositor_keys(keys)? {
+        generator = generator.with_keys(keys, key_assignment)?;
+    }
+    tracing::info!("signing deposits with {} keys", generator.keys().len());
+    if let Some(relayer) = sync {
+        let sync = PoolSync::new(relayer.client.clone(), relayer.url.clone());
+        let pool = rt.block_on(sync.snapshot())?;
+        generator = generator.with_pool(Arc::new(pool));
+    }
+
+    Ok(generator)
+}
+
+/// Proof jobs of every scenario group
+fn scenario_jobs(
+    rt: &Runtime,
+    scenario: &Scenario,
+    relayer: &Relayer,
+    txs_folder: &str,
+) -> Result<Vec<Vec<ProofJob>>, TestError> {
+    let settings = &scenario.corpus;
+    let generator = build_generator(
+        rt,
+        settings.seed,
+        settings.keys,
+        settings.key_assignment,
+        settings.sync.then_some(relayer),
+    )?
+    .with_folder(txs_folder.to_owned());
+
+    let mut groups = vec![];
+    let mut next = 0;
+    for group in &scenario.txs {
+        let amounts = TxAmounts::new(
+            group
+                .amount
+                .unwrap_or(AmountRange::fixed(settings.denominator)),
+            group.fee.unwrap_or(AmountRange::fixed(0)),
+            settings.denominator,
+        )?;
+        let generator = generator.clone().with_amounts(amounts);
+        let withdrawal = WithdrawalParams {
+            receiver: match &group.receiver {
+                Some(receiver) => WithdrawalParams::parse_receiver(receiver)?,
+                None => generator.address(),
+            },
+            native_amount: group.native_amount,
+            energy: group.energy,
+        };
+
+        groups.push(
+            (next..next + group.count)
+                .map(|index| ProofJob {
+                    generator: generator.clone(),
+                    kind: group.tx_type,
+                    index,
+                    outputs: group.outputs,
+                    withdrawal: withdrawal.clone(),
+                })
+                .collect(),
+        );
+        next += group.count;
+    }
+
+    Ok(groups)
+}
+
+/// Picks the files of every scenario group from the corpus manifest
+fn pick_scenario(scenario: &Scenario, txs_folder: &str) -> Result<Vec<Vec<String>>, TestError> {
+    let seed = scenario.corpus.seed;
+    let entries = corpus::read(txs_folder)?.ok_or_else(|| {
+        TestError::ConfigError(format!("picking txs requires the manifest of {}", txs_folder))
+    })?;
+    let mut groups = vec![];
+    for group in &scenario.txs {
+        let available = corpus::select(entries.clone(), Some(group.tx_type.name()), seed);
+        if (available.len() as u64) < group.count {
+            tracing::warn!(
+                "only {} of {} {} txs in {}",
+                available.len(),
+                group.count,
+                group.tx_type.name(),
+                txs_folder
+            );
+        }
+        groups.push(
+            available
+                .into_iter()
+                .take(group.count as usize)
+                .map(|entry| entry.file_name)
+                .collect(),
+        );
+    }
+
+    Ok(groups)
+}
+
+/// Sending order of the scenario groups: drawn by weight if the groups are weighted, one
+/// group after the other otherwise
+fn order_scenario<T>(scenario: &Scenario, groups: Vec<Vec<T>>) -> Result<Vec<T>, TestError> {
+    let mix = match scenario.mix()? {
+        Some(mix) => mix,
+        None => return Ok(groups.into_iter().flatten().collect()),
+    };
+    let mut pools: BTreeMap<TxKind, VecDeque<T>> = BTreeMap::new();
+    for (group, txs) in scenario.txs.iter().zip(groups) {
+        pools.entry(group.tx_type).or_default().extend(txs);
+    }
+    let count = pools.values().map(VecDeque::len).sum();
+
+    Ok(mix.draw(pools, count, &mut mix_rng(scenario.corpus.seed)))
 }
 
 async fn view_results() -> Result<Vec<f64>, TestError> {
@@ -129,8 +1103,11 @@ async fn view_results() -> Result<Vec<f64>, TestError> {
     let reader = BufReader::new(file);
     let relayer_url = env::var("RELAYER_URL").unwrap_or(DEFAULT_RELAYER_URL.to_owned());
     let mut results: Vec<f64> = vec![];
-    for (_index, line) in reader.lines().enumerate() {
-        let job_result: JobResult = serde_json::from_slice(line.unwrap().as_bytes()).unwrap();
+    for line in reader.lines() {
+        let job_result = match serde_json::from_slice(line.unwrap().as_bytes()).unwrap() {
+            SubmitResult::Job(job_result) => job_result,
+            SubmitResult::Failed(_) => continue,
+        };
 
         let job_status: JobStatus =
             reqwest::get(format!("{}/job/{}", relayer_url, job_result.job_id))
@@ -179,9 +1156,9 @@ fn send_to_gw(index: usize) {
             tracing::info!("published {} ", index);
             thread::sleep(Duration::from_millis(100));
 }
-fn publish(target: &Histogram, values: &Vec<f64>, batch_size: usize) {
+fn publish(target: &Histogram, values: &[f64], batch_size: usize) {
 
-    for (index, value) in values.into_iter().enumerate() {
+    for (index, value) in values.iter().enumerate() {
         target.observe(*value);
         if index % batch_size == 0 {
             send_to_gw(index);
@@ -199,47 +1176,165 @@ fn main() -> Result<(), TestError> {
     let args = Args::parse();
     tracing::info!("{:?}", args);
 
+    if let Some(Command::Run { scenario }) = &args.command {
+        return run(Scenario::load(scenario)?);
+    }
+
     let threads: usize = args.threads.into();
 
     let rt = init_runtime(threads);
 
-    match args.mode.as_str() {
-        "generate" => match args.tx_type.as_str() {
-            "deposit" => {
-                rt.block_on(async {
-                    let mut completion_stream = (0..args.count.into())
-                        .map(|_| async {
-                            let thread_name: String = thread::current().name().unwrap().to_owned();
+    match args.mode.as_deref().unwrap_or_default() {
+        "generate" => {
+            let tx_kind: TxKind = args.tx_type.parse()?;
+            let outputs: usize = args.outputs.into();
 
-                            tracing::info!("{} started", thread_name);
+            let relayer = relayer(&args)?;
+            let amounts = TxAmounts::new(args.amount, args.fee, args.denominator)?;
+            let generator = build_generator(
+                &rt,
+                args.seed,
+                args.keys,
+                args.key_assignment,
+                args.sync.then_some(&relayer),
+            )?
+            .with_amounts(amounts);
+            let withdrawal = withdrawal_params(&args, &generator)?;
 
-                            let sk = env::var("SK").unwrap_or(DEFAULT_SK.to_owned());
+            let txs_folder = env::var("TX_FOLDER")
+                .map_err(|_| TestError::ConfigError(String::from("TX_FOLDER not set")))?;
+            let mut manifest = ManifestWriter::open(&txs_folder)?;
 
-                            let generator = Generator::new(sk.as_str());
+            let jobs = (0..u64::from(args.count)).map(|index| ProofJob {
+                generator: generator.clone(),
+                kind: tx_kind,
+                index,
+                outputs,
+                withdrawal: withdrawal.clone(),
+            });
+            generate(&rt, &prover_pool(args.provers), jobs, &mut manifest).map(|_| ())
+        }
+        "chain" => {
+            let relayer = relayer(&args)?;
+            let amounts = TxAmounts::new(args.amount, args.fee, args.denominator)?;
+            // every chain syncs its own account with the relayer
+            let generator =
+                build_generator(&rt, args.seed, args.keys, args.key_assignment, None)?
+                    .with_amounts(amounts);
+            let params = ChainParams {
+                withdrawal: withdrawal_params(&args, &generator)?,
+                job_timeout: Duration::from_secs(args.job_timeout),
+                retry: relayer.retry.clone(),
+            };
 
-                            generator.generate_deposit().await.unwrap()
-                        })
-                        .map(|f| rt.spawn(f))
-                        .collect::<FuturesUnordered<_>>();
+            let prover = prover_pool(args.provers);
+            rt.block_on(async {
+                // every chain syncs the whole pool, so only `threads` of them run at a time
+                let mut completion_stream = stream::iter(0..u64::from(args.count))
+                    .map(|index| {
+                        let mut chain = AccountChain::new(
+                            generator.clone(),
+                            prover.clone(),
+                            relayer.client.clone(),
+                            relayer.url.clone(),
+                            index,
+                        );
+                        let params = params.clone();
+                        async move { chain.run(&params).await }
+                    })
+                    .map(|f| rt.spawn(f))
+                    .buffer_unordered(usize::from(args.threads));
 
-                    while let Some(Ok((file_name, thread_name))) = completion_stream.next().await {
-                        tracing::info!("{} saved {}", thread_name, file_name);
-                    }
+                let mut file = fs::OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open("result.log")?;
 
-                    // for _ in 0..args.count {
-                    //     let sk = env::var("SK").unwrap_or(DEFAULT_SK.to_owned());
-                    //     let generator = Generator::new(sk.as_str());
-                    //     rt.spawn(async move { generator.generate_deposit().await.unwrap() });
-
-                    // }
-                });
+                while let Some(result) = completion_stream.next().await {
+                    match result {
+                        Ok(Ok(job_results)) => {
+                            for job_result in job_results {
+                                let content = serde_json::to_string(&job_result)
+                                    .map_err(TestError::SerializationError)?;
+                                writeln!(file, "{}", content)?;
+                            }
+                        }
+                        Ok(Err(e)) => tracing::error!("account chain failed: {:?}", e),
+                        Err(e) => tracing::error!("account chain task failed: {}", e),
+                    }
+                }
                 Ok(())
+            })
+        }
+        "send" => {
+            let txs_folder = env::var("TX_FOLDER").unwrap_or("./txs".to_owned());
+            let skip: usize = args.skip.into();
+            let count = args.count as usize;
+            let txs = match &args.mix {
+                // a seeded draw skips the txs a previous run with the same seed sent
+                Some(mix) => mix_files(&txs_folder, mix, args.seed, skip + count)?,
+                None => corpus_files(&txs_folder, args.filter_type, args.seed)?,
             }
-            _ => Err(load_runner::utils::TestError::GeneratorError(String::from(
-                "unknown transaction type",
-            ))),
-        },
-        "send" => rt.block_on(async { send(threads, &rt, args.count.into(), args.skip.into()) }),
+            .into_iter()
+            .skip(skip)
+            .take(count)
+            .collect();
+            let relayer = relayer(&args)?;
+            let txs = read_txs(&rt, txs_folder, txs);
+            send(&rt, txs, &send_options(&args), job_tracker(&args, &relayer), &relayer)
+                .map(|_| ())
+        }
+        "run" => {
+            let relayer = relayer(&args)?;
+            let amounts = TxAmounts::new(args.amount, args.fee, args.denominator)?;
+            let generator = build_generator(
+                &rt,
+                args.seed,
+                args.keys,
+                args.key_assignment,
+                args.sync.then_some(&relayer),
+            )?
+            .with_amounts(amounts);
+            let withdrawal = withdrawal_params(&args, &generator)?;
+
+            let total = args.count as usize;
+            // types are drawn and proof jobs built as the provers get to them
+            let kinds: Box<dyn Iterator<Item = TxKind> + Send> = match args.mix.clone() {
+                Some(mix) => {
+                    let mut rng = mix_rng(args.seed);
+                    Box::new(std::iter::repeat_with(move || mix.pick(&mut rng)))
+                }
+                None => Box::new(std::iter::repeat(args.tx_type.parse()?)),
+            };
+            let outputs = args.outputs.into();
+            let jobs = kinds
+                .take(total)
+                .zip(0..)
+                .map(move |(kind, index)| ProofJob {
+                    generator: generator.clone(),
+                    kind,
+                    index,
+                    outputs,
+                    withdrawal: withdrawal.clone(),
+                });
+            let manifest = match args.persist {
+                true => {
+                    let txs_folder = env::var("TX_FOLDER").map_err(|_| {
+                        TestError::ConfigError(String::from("persisting txs requires TX_FOLDER"))
+                    })?;
+                    Some(ManifestWriter::open(&txs_folder)?)
+                }
+                false => None,
+            };
+
+            let provers = ProverOptions {
+                pool: prover_pool(args.provers),
+                queue_size: args.queue_size,
+            };
+            let txs = prove_txs(&rt, jobs, total, provers, manifest);
+            send(&rt, txs, &send_options(&args), job_tracker(&args, &relayer), &relayer)
+                .map(|_| ())
+        }
         "publish" => {
             let batch_size = env::var("BATCH_SIZE").unwrap_or("1".to_string());
             let results = rt.block_on(async { view_results().await }).unwrap();
@@ -276,7 +1371,7 @@ fn publish_test() {
 
         let mut batch_size: usize = 0;
 
-        while batch_size % 10 == 0 {
+        while batch_size.is_multiple_of(10) {
             batch_size = (rand::thread_rng().gen::<usize>()) % 10;
         }
 
@@ -284,7 +1379,7 @@ fn publish_test() {
 
         // let batch_size: usize = rand::thread_rng().gen();
 
-        let values = ints.map(|e| f64::try_from(e % 10).unwrap());
+        let values = ints.map(|e| f64::from(e % 10));
 
         tracing::info!("{:?}", values);
 
@@ -295,3 +1390,85 @@ fn publish_test() {
         publish(&TEST_HISTOGRAM,&v, batch_size % 10);
     }
 }
+
+#[test]
+fn relayer_args_test() {
+    let args = Args::try_parse_from([
+        "load_runner",
+        "--mode",
+        "send",
+        "--max-attempts",
+        "3",
+        "--retry-on",
+        "timeout,503",
+        "--header",
+        "X-Api-Key: 1",
+        "--header",
+        "X-Run: 2",
+    ])
+    .unwrap();
+    let retry = relayer(&args).unwrap().retry;
+
+    assert_eq!(args.headers.len(), 2);
+
+    assert_eq!(retry.max_attempts, 3);
+    assert_eq!(
+        retry.retry_on,
+        vec![
+            Retryable::Error(load_runner::sender::ErrorKind::Timeout),
+            Retryable::Status(503)
+        ]
+    );
+    assert!(
+        Args::try_parse_from(["load_runner", "--mode", "send", "--retry-on", "bogus"]).is_err()
+    );
+}
+
+#[test]
+fn run_args_test() {
+    let args = Args::try_parse_from(["load_runner", "run", "scenarios/example.toml"]).unwrap();
+    assert!(matches!(
+        args.command,
+        Some(Command::Run { scenario }) if scenario == "scenarios/example.toml"
+    ));
+    assert!(Args::try_parse_from(["load_runner"]).is_err());
+
+    let args =
+        Args::try_parse_from(["load_runner", "--mode", "run", "--persist", "--publish"]).unwrap();
+    let options = send_options(&args);
+    assert!(args.persist && options.publish);
+    assert!(matches!(options.load, Load::Open(LoadProfile::Constant { rate }) if rate == 1.0));
+    assert_eq!((args.provers, args.queue_size), (None, 100));
+
+    let args = Args::try_parse_from([
+        "load_runner",
+        "--mode",
+        "run",
+        "--count",
+        "100000",
+        "--provers",
+        "8",
+        "--queue-size",
+        "500",
+    ])
+    .unwrap();
+    assert_eq!((args.count, args.provers, args.queue_size), (100_000, Some(8), 500));
+}
+
+#[test]
+fn mix_args_test() {
+    let args =
+        Args::try_parse_from(["load_runner", "--mode", "send", "--mix", "transfer:6,deposit:4"])
+            .unwrap();
+    assert_eq!(args.mix, Some("deposit:4,transfer:6".parse().unwrap()));
+    assert!(Args::try_parse_from([
+        "load_runner",
+        "--mode",
+        "send",
+        "--mix",
+        "transfer:1",
+        "--filter-type",
+        "deposit",
+    ])
+    .is_err());
+}
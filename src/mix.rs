@@ -0,0 +1,153 @@
+//! Weighted transaction mix. Production traffic interleaves transfers, deposits and
+//! withdrawals, so a run can draw them from typed pools in fixed proportions instead of
+//! replaying a corpus one type after the other.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    str::FromStr,
+};
+
+use rand::Rng;
+
+use crate::{generator::TxKind, utils::TestError};
+
+/// Relative weight of every transaction type, parsed from `TYPE:WEIGHT,...`, e.g.
+/// `transfer:60,deposit:30,withdrawal:10`. Weights needn't add up to 100.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxMix {
+    weights: BTreeMap<TxKind, f64>,
+}
+
+impl TxMix {
+    pub fn new(weights: BTreeMap<TxKind, f64>) -> Result<Self, TestError> {
+        if weights.is_empty() {
+            return Err(TestError::ConfigError(String::from("empty tx mix")));
+        }
+        if let Some((kind, weight)) = weights
+            .iter()
+            .find(|(_, weight)| !weight.is_finite() || **weight <= 0.0)
+        {
+            return Err(TestError::ConfigError(format!(
+                "invalid weight {} of {} txs",
+                weight,
+                kind.name()
+            )));
+        }
+
+        Ok(TxMix { weights })
+    }
+
+    pub fn kinds(&self) -> impl Iterator<Item = TxKind> + '_ {
+        self.weights.keys().copied()
+    }
+
+    /// Picks the type of the next transaction by weight, when every type is available
+    pub fn pick<R: Rng>(&self, rng: &mut R) -> TxKind {
+        pick(self.weights.iter().map(|(kind, weight)| (*kind, *weight)), rng)
+            .expect("a tx mix isn't empty")
+    }
+
+    /// Draws up to `count` transactions from the per-type `pools`, the type of each one picked
+    /// by weight. Pools are drawn in order. A type whose pool runs dry drops out of the mix,
+    /// the other types keep their relative shares.
+    pub fn draw<T, R: Rng>(
+        &self,
+        mut pools: BTreeMap<TxKind, VecDeque<T>>,
+        count: usize,
+        rng: &mut R,
+    ) -> Vec<T> {
+        let mut drawn = Vec::with_capacity(count);
+        while drawn.len() < count {
+            let available = self
+                .weights
+                .iter()
+                .filter(|(kind, _)| pools.get(kind).is_some_and(|pool| !pool.is_empty()))
+                .map(|(kind, weight)| (*kind, *weight));
+            let kind = match pick(available, rng) {
+                Some(kind) => kind,
+                None => break,
+            };
+            drawn.extend(pools.get_mut(&kind).and_then(VecDeque::pop_front));
+        }
+
+        drawn
+    }
+}
+
+/// Weighted pick among `weights`, `None` if there are none
+fn pick<R: Rng>(weights: impl Iterator<Item = (TxKind, f64)>, rng: &mut R) -> Option<TxKind> {
+    let weights: Vec<(TxKind, f64)> = weights.collect();
+    let (last, _) = *weights.last()?;
+
+    let total: f64 = weights.iter().map(|(_, weight)| weight).sum();
+    let mut point = rng.gen_range(0.0..total);
+    let kind = weights
+        .iter()
+        .find(|(_, weight)| {
+            point -= weight;
+            point < 0.0
+        })
+        .map_or(last, |(kind, _)| *kind);
+
+    Some(kind)
+}
+
+impl FromStr for TxMix {
+    type Err = TestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |part: &str| TestError::ConfigError(format!("invalid tx mix part {}", part));
+
+        let mut weights = BTreeMap::new();
+        for part in s.split(',') {
+            let (kind, weight) = part.split_once(':').ok_or_else(|| invalid(part))?;
+            let kind: TxKind = kind.trim().parse()?;
+            let weight: f64 = weight.trim().parse().map_err(|_| invalid(part))?;
+            if weights.insert(kind, weight).is_some() {
+                return Err(TestError::ConfigError(format!(
+                    "{} txs are mixed in twice",
+                    kind.name()
+                )));
+            }
+        }
+
+        TxMix::new(weights)
+    }
+}
+
+#[test]
+fn tx_mix_test() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let mix: TxMix = "transfer:60,deposit:30,withdrawal:10".parse().unwrap();
+    let pool = |kind: TxKind, len: usize| (kind, (0..len).map(|i| (kind, i)).collect());
+    let pools = BTreeMap::from([
+        pool(TxKind::Deposit, 1000),
+        pool(TxKind::Transfer, 1000),
+        pool(TxKind::Withdrawal, 50),
+    ]);
+
+    let drawn = mix.draw(pools, 1000, &mut StdRng::seed_from_u64(1));
+    let of = |kind: TxKind| drawn.iter().filter(move |(drawn, _)| *drawn == kind);
+
+    assert_eq!(drawn.len(), 1000);
+    // about 100 withdrawals would have been drawn, the pool ran dry at 50
+    assert_eq!(of(TxKind::Withdrawal).count(), 50);
+    let ratio = of(TxKind::Transfer).count() as f64 / of(TxKind::Deposit).count() as f64;
+    assert!((ratio - 2.0).abs() < 0.3, "ratio {}", ratio);
+    assert!(of(TxKind::Deposit)
+        .map(|(_, i)| *i)
+        .eq(0..of(TxKind::Deposit).count()));
+
+    // picking one type at a time matches a draw from pools that never run dry
+    let full = BTreeMap::from([pool(TxKind::Deposit, 100), pool(TxKind::Transfer, 100)]);
+    let mix: TxMix = "transfer:60,deposit:30".parse().unwrap();
+    let mut rng = StdRng::seed_from_u64(2);
+    let picked: Vec<TxKind> = (0..100).map(|_| mix.pick(&mut rng)).collect();
+    let drawn = mix.draw(full, 100, &mut StdRng::seed_from_u64(2));
+    assert!(drawn.iter().map(|(kind, _)| *kind).eq(picked));
+
+    assert!("transfer:60,transfer:40".parse::<TxMix>().is_err());
+    assert!("transfer:0".parse::<TxMix>().is_err());
+    assert!("transfer".parse::<TxMix>().is_err());
+}
@@ -0,0 +1,261 @@
+//! Bounded queue between the prover workers and the sender. Proving is much slower than
+//! sending, so the queue keeps proved txs ready for the sender and its counters tell whether
+//! the provers keep up with the configured rate.
+
+use std::{
+    sync::{
+        atomic::{AtomicIsize, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use tokio::{
+    sync::mpsc::{self, error::TryRecvError},
+    time::Instant,
+};
+
+use crate::latency::{LatencyHistogram, Quantiles};
+
+/// Counters shared by both ends of the queue
+#[derive(Debug)]
+pub struct PipelineStats {
+    /// Txs waiting in the queue. Counted once a send went through, so the sender may take a
+    /// tx before it is counted and push this below zero for a moment.
+    depth: AtomicIsize,
+    pub proved: AtomicUsize,
+    /// Txs that couldn't be proved
+    pub failed: AtomicUsize,
+    /// Proving time of every tx
+    pub proof_times: Mutex<LatencyHistogram>,
+    /// How late the txs the sender had to wait for came out of the queue
+    pub starvation: Mutex<LatencyHistogram>,
+    depths: Mutex<Histogram<u64>>,
+}
+
+impl Default for PipelineStats {
+    fn default() -> Self {
+        PipelineStats {
+            depth: AtomicIsize::new(0),
+            proved: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            proof_times: Mutex::default(),
+            starvation: Mutex::default(),
+            depths: Mutex::new(Histogram::new(3).expect("histogram precision is valid")),
+        }
+    }
+}
+
+impl PipelineStats {
+    pub fn record_proof(&self, proof_time: Duration) {
+        self.proved.fetch_add(1, Ordering::Relaxed);
+        self.proof_times
+            .lock()
+            .unwrap()
+            .record(proof_time.as_secs_f64());
+    }
+
+    /// Txs waiting in the queue
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed).max(0) as usize
+    }
+
+    /// Records the current queue depth, called at a fixed interval
+    pub fn sample_depth(&self) {
+        let depth = self.depth() as u64;
+        self.depths.lock().unwrap().saturating_record(depth);
+    }
+
+    /// `elapsed` is the duration of the run, for the prover throughput
+    pub fn summary(&self, elapsed: Duration) -> PipelineSummary {
+        let proved = self.proved.load(Ordering::Relaxed);
+        let depths = self.depths.lock().unwrap();
+
+        PipelineSummary {
+            proved,
+            failed: self.failed.load(Ordering::Relaxed),
+            prover_rate: proved as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            proof_time: self.proof_times.lock().unwrap().quantiles(),
+            queue_depth_p50: depths.value_at_quantile(0.5),
+            queue_depth_max: depths.max(),
+            starvation: self.starvation.lock().unwrap().quantiles(),
+        }
+    }
+}
+
+/// Send times of a profile, pushed back by the time the sender waited for the provers. A
+/// starved tx delays every tx behind it, which is not the relayer's doing either.
+#[derive(Debug, Default)]
+pub struct Schedule {
+    shift: Duration,
+}
+
+impl Schedule {
+    /// When the tx the profile put at `offset` is due
+    pub fn due(&self, offset: Duration) -> Duration {
+        offset + self.shift
+    }
+
+    /// The tx `due` was asked for at `asked` and came out of the queue at `taken`
+    pub fn taken(&mut self, asked: Instant, due: Instant, taken: Instant) {
+        self.shift += taken.saturating_duration_since(asked.max(due));
+    }
+}
+
+/// Prover and queue side of a run
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineSummary {
+    pub proved: usize,
+    pub failed: usize,
+    /// Proved txs per second
+    pub prover_rate: f64,
+    pub proof_time: Quantiles,
+    /// Queue depth sampled every second
+    pub queue_depth_p50: u64,
+    pub queue_depth_max: u64,
+    /// Seconds the sender waited past the due time of a tx because the queue was empty, the
+    /// count is the number of txs it waited for
+    pub starvation: Quantiles,
+}
+
+/// Queue holding at most `capacity` txs
+pub fn queue<T>(capacity: usize, stats: Arc<PipelineStats>) -> (QueueSender<T>, QueueReceiver<T>) {
+    let (sender, rx) = mpsc::channel(capacity.max(1));
+    (
+        QueueSender {
+            sender,
+            stats: stats.clone(),
+        },
+        QueueReceiver { rx, stats },
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct QueueSender<T> {
+    sender: mpsc::Sender<T>,
+    stats: Arc<PipelineStats>,
+}
+
+impl<T> QueueSender<T> {
+    /// Waits for room in the queue, `false` if the sender is gone
+    pub async fn send(&self, tx: T) -> bool {
+        let sent = self.sender.send(tx).await.is_ok();
+        if sent {
+            self.stats.depth.fetch_add(1, Ordering::Relaxed);
+        }
+        sent
+    }
+
+    pub fn stats(&self) -> &Arc<PipelineStats> {
+        &self.stats
+    }
+}
+
+#[derive(Debug)]
+pub struct QueueReceiver<T> {
+    rx: mpsc::Receiver<T>,
+    stats: Arc<PipelineStats>,
+}
+
+impl<T> QueueReceiver<T> {
+    /// Takes the next tx, `None` once the producer is done. If the queue is empty, the time
+    /// the tx arrives after it was `due` counts as starvation.
+    pub async fn take(&mut self, due: Instant) -> Option<T> {
+        let tx = match self.rx.try_recv() {
+            Ok(tx) => Some(tx),
+            Err(TryRecvError::Disconnected) => None,
+            Err(TryRecvError::Empty) => {
+                let tx = self.rx.recv().await;
+                let late = Instant::now().saturating_duration_since(due);
+                if tx.is_some() && late > Duration::ZERO {
+                    self.stats
+                        .starvation
+                        .lock()
+                        .unwrap()
+                        .record(late.as_secs_f64());
+                }
+                tx
+            }
+        };
+        if tx.is_some() {
+            self.stats.depth.fetch_sub(1, Ordering::Relaxed);
+        }
+        tx
+    }
+
+    pub fn stats(&self) -> &Arc<PipelineStats> {
+        &self.stats
+    }
+}
+
+#[test]
+fn queue_test() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let stats = Arc::new(PipelineStats::default());
+    let (sender, mut receiver) = queue(2, stats.clone());
+
+    rt.block_on(async {
+        assert!(sender.send(1).await);
+        assert!(sender.send(2).await);
+
+        // a prover waiting for room is not in the queue yet
+        let blocked = sender.clone();
+        let waiting = tokio::spawn(async move { blocked.send(0).await });
+        tokio::task::yield_now().await;
+        assert_eq!(stats.depth(), 2);
+        stats.sample_depth();
+
+        // ready before it is due
+        assert_eq!(receiver.take(Instant::now()).await, Some(1));
+        assert_eq!(receiver.take(Instant::now()).await, Some(2));
+        assert!(waiting.await.unwrap());
+        assert_eq!(receiver.take(Instant::now()).await, Some(0));
+        assert!(stats.starvation.lock().unwrap().is_empty());
+
+        // the sender waits 50ms for the prover
+        let due = Instant::now();
+        let producer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            sender.send(3).await
+        });
+        assert_eq!(receiver.take(due).await, Some(3));
+        assert!(producer.await.unwrap());
+        assert_eq!(receiver.take(due).await, None);
+    });
+
+    stats.record_proof(Duration::from_secs(2));
+    let summary = stats.summary(Duration::from_secs(4));
+    assert_eq!(summary.queue_depth_max, 2);
+    assert_eq!(summary.starvation.count, 1);
+    assert!(summary.starvation.max >= 0.05);
+    assert_eq!(summary.prover_rate, 0.25);
+    assert_eq!(stats.depth(), 0);
+}
+
+#[test]
+fn schedule_test() {
+    let start = Instant::now();
+    let mut schedule = Schedule::default();
+
+    // the provers stall once, on the second tx
+    let scheduled: Vec<Duration> = [0, 10, 20, 30]
+        .into_iter()
+        .map(Duration::from_millis)
+        .enumerate()
+        .map(|(index, offset)| {
+            let due = start + schedule.due(offset);
+            let taken = if index == 1 { due + Duration::from_millis(50) } else { due };
+            schedule.taken(start, due, taken);
+            schedule.due(offset)
+        })
+        .collect();
+
+    let millis: Vec<u64> = scheduled.iter().map(|due| due.as_millis() as u64).collect();
+    assert_eq!(millis, [0, 60, 70, 80]);
+}
@@ -0,0 +1,303 @@
+//! Open-model load profiles: transactions are sent at a target arrival rate that changes over
+//! time, whatever the number of worker threads and however long the relayer takes to answer.
+
+use std::{str::FromStr, time::Duration};
+
+use crate::utils::TestError;
+
+/// Resolution of the arrival schedule
+const TICK: Duration = Duration::from_millis(1);
+
+/// Target arrival rate in transactions per second over time
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadProfile {
+    /// `constant:RATE`
+    Constant { rate: f64 },
+    /// `ramp:FROM:TO:SECS`, linear from `from` to `to` over `duration`, then stays at `to`
+    Ramp { from: f64, to: f64, duration: Duration },
+    /// `step:START:INCREMENT:SECS`, grows by `increment` every `duration`
+    Step {
+        start: f64,
+        increment: f64,
+        duration: Duration,
+    },
+    /// `spike:BASE:PEAK:AT:SECS`, `peak` from `at` for `duration`, `base` otherwise
+    Spike {
+        base: f64,
+        peak: f64,
+        at: Duration,
+        duration: Duration,
+    },
+    /// Profiles run one after the other, each for its `duration` and timed from its own start.
+    /// The last one keeps going.
+    Stages(Vec<LoadStage>),
+}
+
+/// Endless arrival offsets of a validated profile
+#[derive(Debug, Clone)]
+pub struct Arrivals {
+    profile: LoadProfile,
+    sent: u64,
+    expected: f64,
+    elapsed: Duration,
+}
+
+impl Iterator for Arrivals {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        while self.expected < self.sent as f64 {
+            self.expected += self.profile.rate(self.elapsed + TICK / 2) * TICK.as_secs_f64();
+            self.elapsed += TICK;
+        }
+        self.sent += 1;
+        Some(self.elapsed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadStage {
+    pub profile: LoadProfile,
+    pub duration: Duration,
+}
+
+impl LoadProfile {
+    /// Target rate at `elapsed` since the start of the run
+    pub fn rate(&self, elapsed: Duration) -> f64 {
+        match *self {
+            LoadProfile::Stages(ref stages) => {
+                let mut start = Duration::ZERO;
+                for (index, stage) in stages.iter().enumerate() {
+                    if index + 1 == stages.len() || elapsed < start + stage.duration {
+                        return stage.profile.rate(elapsed - start);
+                    }
+                    start += stage.duration;
+                }
+                0.0
+            }
+            LoadProfile::Constant { rate } => rate,
+            LoadProfile::Ramp { from, to, duration } => {
+                if elapsed >= duration {
+                    to
+                } else {
+                    from + (to - from) * elapsed.as_secs_f64() / duration.as_secs_f64()
+                }
+            }
+            LoadProfile::Step {
+                start,
+                increment,
+                duration,
+            } => start + increment * (elapsed.as_secs_f64() / duration.as_secs_f64()).floor(),
+            LoadProfile::Spike {
+                base,
+                peak,
+                at,
+                duration,
+            } => {
+                if elapsed >= at && elapsed < at + duration {
+                    peak
+                } else {
+                    base
+                }
+            }
+        }
+    }
+
+    /// Offsets from the start of the run at which transactions are sent, computed as they are
+    /// taken so a long run needn't be scheduled up front. The `n`-th transaction goes out once
+    /// `n` arrivals are expected by the integrated rate.
+    pub fn arrivals(&self) -> Arrivals {
+        Arrivals {
+            profile: self.clone(),
+            sent: 0,
+            expected: 0.0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Offsets of the first `count` arrivals
+    pub fn schedule(&self, count: usize) -> Vec<Duration> {
+        self.arrivals().take(count).collect()
+    }
+
+    /// Rates can't be negative and every profile has to settle on a positive rate, otherwise
+    /// the schedule never ends
+    pub fn validate(self) -> Result<Self, TestError> {
+        if self
+            .rates()
+            .iter()
+            .any(|rate| !rate.is_finite() || *rate < 0.0)
+        {
+            return Err(TestError::ConfigError(format!(
+                "load profile {:?} has a negative rate",
+                self
+            )));
+        }
+        if !self.settles() {
+            return Err(TestError::ConfigError(format!(
+                "load profile {:?} never reaches a positive rate",
+                self
+            )));
+        }
+
+        Ok(self)
+    }
+
+    fn rates(&self) -> Vec<f64> {
+        match self {
+            LoadProfile::Constant { rate } => vec![*rate],
+            LoadProfile::Ramp { from, to, .. } => vec![*from, *to],
+            LoadProfile::Step {
+                start, increment, ..
+            } => vec![*start, *increment],
+            LoadProfile::Spike { base, peak, .. } => vec![*base, *peak],
+            LoadProfile::Stages(stages) => stages
+                .iter()
+                .flat_map(|stage| stage.profile.rates())
+                .collect(),
+        }
+    }
+
+    fn settles(&self) -> bool {
+        match self {
+            LoadProfile::Constant { rate } => *rate > 0.0,
+            LoadProfile::Ramp { to, .. } => *to > 0.0,
+            LoadProfile::Step {
+                start, increment, ..
+            } => *start > 0.0 || *increment > 0.0,
+            LoadProfile::Spike { base, .. } => *base > 0.0,
+            LoadProfile::Stages(stages) => stages
+                .last()
+                .is_some_and(|stage| stage.profile.settles()),
+        }
+    }
+}
+
+impl FromStr for LoadProfile {
+    type Err = TestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            TestError::ConfigError(format!(
+                "invalid load profile {}, expected constant:RATE, ramp:FROM:TO:SECS, \
+                 step:START:INCREMENT:SECS or spike:BASE:PEAK:AT:SECS",
+                s
+            ))
+        };
+
+        let mut parts = s.split(':');
+        let shape = parts.next().unwrap_or_default();
+        let values = parts
+            .map(|part| part.parse::<f64>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let secs = |value: f64| Duration::try_from_secs_f64(value).map_err(|_| invalid());
+
+        let profile = match (shape, values.as_slice()) {
+            ("constant", [rate]) => LoadProfile::Constant { rate: *rate },
+            ("ramp", [from, to, duration]) => LoadProfile::Ramp {
+                from: *from,
+                to: *to,
+                duration: secs(*duration)?,
+            },
+            ("step", [start, increment, duration]) => LoadProfile::Step {
+                start: *start,
+                increment: *increment,
+                duration: secs(*duration)?,
+            },
+            ("spike", [base, peak, at, duration]) => LoadProfile::Spike {
+                base: *base,
+                peak: *peak,
+                at: secs(*at)?,
+                duration: secs(*duration)?,
+            },
+            _ => return Err(invalid()),
+        };
+
+        match profile {
+            LoadProfile::Ramp { duration, .. } | LoadProfile::Step { duration, .. }
+                if duration.is_zero() =>
+            {
+                Err(invalid())
+            }
+            profile => profile.validate(),
+        }
+    }
+}
+
+#[test]
+fn load_profile_parse_test() {
+    assert_eq!(
+        "constant:2.5".parse::<LoadProfile>().unwrap(),
+        LoadProfile::Constant { rate: 2.5 }
+    );
+    assert_eq!(
+        "spike:1:20:30:5".parse::<LoadProfile>().unwrap(),
+        LoadProfile::Spike {
+            base: 1.0,
+            peak: 20.0,
+            at: Duration::from_secs(30),
+            duration: Duration::from_secs(5),
+        }
+    );
+    assert!("constant:0".parse::<LoadProfile>().is_err());
+    assert!("ramp:1:0:10".parse::<LoadProfile>().is_err());
+    assert!("step:1:1:0".parse::<LoadProfile>().is_err());
+    assert!("sine:1".parse::<LoadProfile>().is_err());
+}
+
+#[test]
+fn load_profile_schedule_test() {
+    let close = |actual: Duration, expected: f64| (actual.as_secs_f64() - expected).abs() < 0.01;
+
+    let constant = LoadProfile::Constant { rate: 10.0 }.schedule(5);
+    assert!(constant
+        .iter()
+        .enumerate()
+        .all(|(n, at)| close(*at, n as f64 / 10.0)));
+
+    // integrated rate t^2 / 2 reaches n at sqrt(2n)
+    let ramp: LoadProfile = "ramp:0:10:10".parse().unwrap();
+    assert!(close(ramp.schedule(3)[2], 2.0));
+
+    let step: LoadProfile = "step:1:1:2".parse().unwrap();
+    assert_eq!(step.rate(Duration::from_secs(5)), 3.0);
+    assert!(close(step.schedule(5)[4], 3.0));
+
+    // a soak test only schedules the arrivals before its deadline
+    let deadline = Duration::from_secs(2);
+    let soak = LoadProfile::Constant { rate: 100.0 }
+        .arrivals()
+        .take(u32::MAX as usize)
+        .take_while(|at| *at < deadline);
+    assert!((199..=201).contains(&soak.count()));
+}
+
+#[test]
+fn load_stages_test() {
+    let stages = LoadProfile::Stages(vec![
+        LoadStage {
+            profile: "constant:10".parse().unwrap(),
+            duration: Duration::from_secs(1),
+        },
+        LoadStage {
+            profile: LoadProfile::Constant { rate: 0.0 },
+            duration: Duration::from_secs(2),
+        },
+        LoadStage {
+            profile: "constant:1".parse().unwrap(),
+            duration: Duration::ZERO,
+        },
+    ])
+    .validate()
+    .unwrap();
+
+    assert_eq!(stages.rate(Duration::from_millis(500)), 10.0);
+    assert_eq!(stages.rate(Duration::from_millis(1500)), 0.0);
+    assert_eq!(stages.rate(Duration::from_secs(60)), 1.0);
+    // 10 txs a second, then a pause until the last stage starts at 3s
+    let schedule = stages.schedule(12);
+    assert!(schedule[9] < Duration::from_secs(1));
+    assert!(schedule[11] >= Duration::from_secs(3));
+
+    assert!(LoadProfile::Stages(vec![]).validate().is_err());
+}
@@ -0,0 +1,83 @@
+//! Thread pool for proving. Groth16 proofs keep the cores busy for seconds, run on the async
+//! runtime they would hold up the HTTP tasks, so they get their own threads.
+
+use threadpool::ThreadPool;
+use tokio::sync::oneshot;
+
+use crate::utils::TestError;
+
+#[derive(Debug, Clone)]
+pub struct ProverPool {
+    pool: ThreadPool,
+}
+
+/// bellman already spreads every proof over all cores, more provers only compete for them
+/// and stretch the proof times. A second one keeps the cores busy while the other builds
+/// its witness, which is single threaded.
+pub const DEFAULT_PROVERS: usize = 2;
+
+impl Default for ProverPool {
+    fn default() -> Self {
+        ProverPool::new(DEFAULT_PROVERS)
+    }
+}
+
+impl ProverPool {
+    pub fn new(size: usize) -> Self {
+        ProverPool {
+            pool: threadpool::Builder::new()
+                .num_threads(size.max(1))
+                .thread_name(String::from("provers"))
+                .build(),
+        }
+    }
+
+    /// Number of proofs built at a time
+    pub fn size(&self) -> usize {
+        self.pool.max_count()
+    }
+
+    /// Runs `prove` on the pool, waits after the jobs queued before it
+    pub async fn run<T, F>(&self, prove: F) -> Result<T, TestError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, TestError> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        self.pool.execute(move || {
+            // the caller may be gone, e.g. cut off by the deadline
+            let _ = sender.send(prove());
+        });
+
+        receiver
+            .await
+            .map_err(|_| TestError::GeneratorError(String::from("prover thread panicked")))?
+    }
+}
+
+#[test]
+fn prover_pool_test() {
+    use std::thread;
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let pool = ProverPool::new(2);
+    assert_eq!(pool.size(), 2);
+
+    rt.block_on(async {
+        let name = pool
+            .run(|| Ok(thread::current().name().map(str::to_owned)))
+            .await
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("provers"));
+
+        let failed: Result<(), _> = pool
+            .run(|| Err(TestError::GeneratorError(String::from("no notes"))))
+            .await;
+        assert!(matches!(failed, Err(TestError::GeneratorError(e)) if e == "no notes"));
+
+        let panicked: Result<(), _> = pool.run(|| panic!("prover bug")).await;
+        assert!(panicked.is_err());
+    });
+}
@@ -0,0 +1,125 @@
+//! Retries of relayer requests. Wallets retry transient failures such as rate limiting or an
+//! overloaded relayer, so the load runner can do the same instead of sending every tx once.
+
+use std::{str::FromStr, time::Duration};
+
+use rand::Rng;
+
+use crate::{sender::ErrorKind, utils::TestError};
+
+/// Failure a request is retried on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retryable {
+    /// An error status not classified as a relayer error, e.g. `429` or `503`
+    Status(u16),
+    /// A failure cause, e.g. `timeout` or `connect`
+    Error(ErrorKind),
+}
+
+impl FromStr for Retryable {
+    type Err = TestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<u16>() {
+            Ok(status) => Ok(Retryable::Status(status)),
+            Err(_) => s.parse().map(Retryable::Error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts per request, the first one included
+    pub max_attempts: u32,
+    /// Backoff before the first retry, doubled for every further one
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    pub retry_on: Vec<Retryable>,
+}
+
+impl Default for RetryPolicy {
+    /// A single attempt
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            retry_on: vec![
+                Retryable::Error(ErrorKind::Timeout),
+                Retryable::Error(ErrorKind::Connect),
+                Retryable::Status(429),
+                Retryable::Status(500),
+                Retryable::Status(502),
+                Retryable::Status(503),
+                Retryable::Status(504),
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether a failure is worth another attempt. Statuses only match failures that aren't
+    /// classified further, so a listed `500` doesn't retry an invalid proof.
+    pub fn is_retryable(&self, kind: ErrorKind, status: Option<u16>) -> bool {
+        self.retry_on.iter().any(|retryable| match retryable {
+            Retryable::Error(retryable) => *retryable == kind,
+            Retryable::Status(retryable) => kind == ErrorKind::Http && status == Some(*retryable),
+        })
+    }
+
+    /// Delay before the next attempt after `attempts` failed ones, `None` if the request
+    /// shouldn't be retried
+    pub fn retry_after(
+        &self,
+        error: &TestError,
+        status: Option<u16>,
+        attempts: u32,
+    ) -> Option<Duration> {
+        if attempts >= self.max_attempts
+            || !self.is_retryable(ErrorKind::classify(error, status), status)
+        {
+            return None;
+        }
+
+        Some(self.backoff(attempts))
+    }
+
+    /// Exponential backoff with full jitter: uniform between zero and
+    /// `backoff * 2^(attempts - 1)`, capped at `max_backoff`
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(31);
+        let ceiling = self
+            .backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+
+        rand::thread_rng().gen_range(Duration::ZERO..=ceiling)
+    }
+}
+
+#[test]
+fn retry_policy_test() {
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        retry_on: "timeout,503"
+            .split(',')
+            .map(|s| s.parse().unwrap())
+            .collect(),
+        ..RetryPolicy::default()
+    };
+
+    assert!(policy.is_retryable(ErrorKind::Timeout, None));
+    assert!(policy.is_retryable(ErrorKind::Http, Some(503)));
+    assert!(!policy.is_retryable(ErrorKind::Http, Some(500)));
+    assert!(!policy.is_retryable(ErrorKind::InvalidProof, Some(503)));
+
+    let unavailable = TestError::BadResponse(String::from("service unavailable"));
+    assert!(policy.retry_after(&unavailable, Some(503), 2).is_some());
+    assert!(policy.retry_after(&unavailable, Some(503), 3).is_none());
+
+    for attempts in 1..10 {
+        assert!(policy.backoff(attempts) <= Duration::from_millis(200 << (attempts - 1)));
+        assert!(policy.backoff(attempts) <= policy.max_backoff);
+    }
+    assert!("sometimes".parse::<Retryable>().is_err());
+}
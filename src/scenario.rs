@@ -0,0 +1,603 @@
+//! Scenario files: a whole load test, from the relayer it targets to the thresholds it has to
+//! meet, written in TOML or YAML so test definitions can be versioned and reviewed. Secrets
+//! stay in the environment: `SK`, `MNEMONIC`, `KEY_FILE` and `RELAYER_AUTH`.
+
+use std::{
+    collections::BTreeMap, ffi::OsStr, fmt::Display, fs, path::Path, str::FromStr, time::Duration,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::{
+    client::{Header, HttpConfig},
+    depositors::KeyAssignment,
+    generator::{AmountRange, TxKind},
+    latency::Quantiles,
+    mix::TxMix,
+    pipeline::PipelineSummary,
+    profile::{LoadProfile, LoadStage},
+    retry::{RetryPolicy, Retryable},
+    tracker::POLL_CONCURRENCY,
+    utils::TestError,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: String,
+    /// Worker threads of the runtime
+    #[serde(default = "default_threads")]
+    pub threads: usize,
+    #[serde(default)]
+    pub relayer: RelayerSettings,
+    #[serde(default)]
+    pub corpus: CorpusSettings,
+    /// Transactions to send, generated first if `corpus.generate` is set. Sent in generation
+    /// order, or interleaved by weight if the groups are weighted.
+    #[serde(default)]
+    pub txs: Vec<TxGroup>,
+    pub load: LoadSettings,
+    /// Sending stops after this many seconds
+    pub duration_secs: Option<u64>,
+    #[serde(default)]
+    pub thresholds: Thresholds,
+    #[serde(default)]
+    pub outputs: Outputs,
+}
+
+impl Scenario {
+    /// Reads a `.toml`, `.yaml` or `.yml` scenario file
+    pub fn load(path: &str) -> Result<Self, TestError> {
+        let content = fs::read_to_string(path)?;
+        let invalid =
+            |e: &dyn Display| TestError::ConfigError(format!("invalid scenario {}: {}", path, e));
+
+        let scenario: Scenario = match Path::new(path).extension().and_then(OsStr::to_str) {
+            Some("toml") => toml::from_str(&content).map_err(|e| invalid(&e))?,
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&content).map_err(|e| invalid(&e))?
+            }
+            _ => return Err(invalid(&"expected a .toml, .yaml or .yml file")),
+        };
+        scenario.load.profile().map_err(|e| invalid(&e))?;
+        scenario.mix().map_err(|e| invalid(&e))?;
+
+        Ok(scenario)
+    }
+
+    /// Mix of the weighted `txs` groups, `None` if they have no weights. Weights of groups of
+    /// the same type add up.
+    pub fn mix(&self) -> Result<Option<TxMix>, TestError> {
+        let weighted = self.txs.iter().filter(|group| group.weight.is_some()).count();
+        if weighted == 0 {
+            return Ok(None);
+        }
+        if weighted < self.txs.len() {
+            return Err(TestError::ConfigError(String::from(
+                "either every txs group has a weight or none",
+            )));
+        }
+
+        let mut weights = BTreeMap::new();
+        for group in &self.txs {
+            *weights.entry(group.tx_type).or_default() += group.weight.unwrap_or_default();
+        }
+        TxMix::new(weights).map(Some)
+    }
+}
+
+fn default_threads() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelayerSettings {
+    /// Defaults to `RELAYER_URL`
+    pub url: Option<String>,
+    pub request_timeout_ms: Option<u64>,
+    pub connect_timeout_ms: Option<u64>,
+    pub pool_max_idle: Option<usize>,
+    pub pool_idle_timeout_secs: Option<u64>,
+    pub tcp_keepalive_secs: Option<u64>,
+    #[serde(default)]
+    pub http2: bool,
+    pub proxy: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub retry: RetrySettings,
+}
+
+impl RelayerSettings {
+    /// Client settings, the `Authorization` header has to come from the environment
+    pub fn http_config(&self, authorization: Option<String>) -> Result<HttpConfig, TestError> {
+        let defaults = HttpConfig::default();
+
+        Ok(HttpConfig {
+            timeout: self
+                .request_timeout_ms
+                .map_or(defaults.timeout, Duration::from_millis),
+            connect_timeout: self.connect_timeout_ms.map(Duration::from_millis),
+            pool_max_idle: self.pool_max_idle,
+            pool_idle_timeout: self
+                .pool_idle_timeout_secs
+                .map_or(defaults.pool_idle_timeout, Duration::from_secs),
+            tcp_keepalive: self.tcp_keepalive_secs.map(Duration::from_secs),
+            http2: self.http2,
+            proxy: self.proxy.clone(),
+            headers: self
+                .headers
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value).parse())
+                .collect::<Result<Vec<Header>, _>>()?,
+            authorization,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetrySettings {
+    pub max_attempts: Option<u32>,
+    pub backoff_ms: Option<u64>,
+    pub max_backoff_ms: Option<u64>,
+    pub retry_on: Option<Vec<String>>,
+}
+
+impl RetrySettings {
+    pub fn policy(&self) -> Result<RetryPolicy, TestError> {
+        let defaults = RetryPolicy::default();
+
+        Ok(RetryPolicy {
+            max_attempts: self.max_attempts.unwrap_or(defaults.max_attempts).max(1),
+            backoff: self
+                .backoff_ms
+                .map_or(defaults.backoff, Duration::from_millis),
+            max_backoff: self
+                .max_backoff_ms
+                .map_or(defaults.max_backoff, Duration::from_millis),
+            retry_on: match &self.retry_on {
+                Some(retry_on) => retry_on
+                    .iter()
+                    .map(|retryable| retryable.parse())
+                    .collect::<Result<Vec<Retryable>, _>>()?,
+                None => defaults.retry_on,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorpusSettings {
+    /// Defaults to `TX_FOLDER`
+    pub folder: Option<String>,
+    /// Prove `txs` and send them as soon as they are ready, otherwise they are picked from
+    /// the corpus manifest
+    #[serde(default)]
+    pub generate: bool,
+    /// Also save generated transactions and their manifest to the folder
+    #[serde(default)]
+    pub persist: bool,
+    /// Proving threads, defaults to `DEFAULT_PROVERS`
+    pub provers: Option<usize>,
+    /// Proved transactions kept ready for the sender
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+    /// Seed of generated transactions, or of the transactions picked from the corpus
+    pub seed: Option<u64>,
+    /// Build generated transactions on top of the relayer pool state
+    #[serde(default)]
+    pub sync: bool,
+    /// Pool token denominator
+    #[serde(default = "default_denominator")]
+    pub denominator: u64,
+    /// Depositor keys derived from `MNEMONIC`
+    #[serde(default = "default_keys")]
+    pub keys: u32,
+    #[serde(default = "default_key_assignment", deserialize_with = "parse")]
+    pub key_assignment: KeyAssignment,
+}
+
+impl Default for CorpusSettings {
+    fn default() -> Self {
+        CorpusSettings {
+            folder: None,
+            generate: false,
+            persist: false,
+            provers: None,
+            queue_size: default_queue_size(),
+            seed: None,
+            sync: false,
+            denominator: default_denominator(),
+            keys: default_keys(),
+            key_assignment: default_key_assignment(),
+        }
+    }
+}
+
+fn default_denominator() -> u64 {
+    1_000_000_000
+}
+
+fn default_queue_size() -> usize {
+    100
+}
+
+fn default_keys() -> u32 {
+    10
+}
+
+fn default_key_assignment() -> KeyAssignment {
+    KeyAssignment::RoundRobin
+}
+
+/// `count` transactions of one type
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TxGroup {
+    #[serde(rename = "type", deserialize_with = "parse")]
+    pub tx_type: TxKind,
+    pub count: u64,
+    /// Share of the group in the sent mix, relative to the weights of the other groups
+    pub weight: Option<f64>,
+    /// Output notes of transfers
+    #[serde(default = "default_outputs")]
+    pub outputs: usize,
+    /// Token amount deposited, `N` or `MIN..MAX`, one pool unit by default
+    #[serde(default, deserialize_with = "parse_option")]
+    pub amount: Option<AmountRange>,
+    /// Token fee, `N` or `MIN..MAX`, none by default
+    #[serde(default, deserialize_with = "parse_option")]
+    pub fee: Option<AmountRange>,
+    /// Withdrawal receiver address, defaults to the depositor address
+    pub receiver: Option<String>,
+    #[serde(default)]
+    pub native_amount: u64,
+    #[serde(default)]
+    pub energy: u64,
+}
+
+fn default_outputs() -> usize {
+    1
+}
+
+/// Either an open-model `profile` or `stages`, or closed-model `users`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoadSettings {
+    #[serde(default, deserialize_with = "parse_option")]
+    pub profile: Option<LoadProfile>,
+    #[serde(default)]
+    pub stages: Vec<StageSettings>,
+    pub users: Option<usize>,
+    #[serde(default = "default_job_timeout")]
+    pub job_timeout_secs: u64,
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval_ms: u64,
+    /// Relayer jobs polled at a time
+    #[serde(default = "default_poll_concurrency")]
+    pub poll_concurrency: usize,
+}
+
+impl LoadSettings {
+    /// The arrival-rate profile, `None` for closed-model users
+    pub fn profile(&self) -> Result<Option<LoadProfile>, TestError> {
+        let stages = (!self.stages.is_empty()).then(|| {
+            LoadProfile::Stages(
+                self.stages
+                    .iter()
+                    .map(|stage| LoadStage {
+                        profile: stage.profile.clone(),
+                        duration: Duration::from_secs(stage.duration_secs),
+                    })
+                    .collect(),
+            )
+        });
+
+        match (self.users, &self.profile, stages) {
+            (Some(_), None, None) => Ok(None),
+            (None, Some(profile), None) => Ok(Some(profile.clone())),
+            (None, None, Some(stages)) => stages.validate().map(Some),
+            _ => Err(TestError::ConfigError(String::from(
+                "load needs exactly one of profile, stages or users",
+            ))),
+        }
+    }
+}
+
+fn default_job_timeout() -> u64 {
+    120
+}
+
+fn default_poll_interval() -> u64 {
+    500
+}
+
+fn default_poll_concurrency() -> usize {
+    POLL_CONCURRENCY
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageSettings {
+    #[serde(deserialize_with = "parse")]
+    pub profile: LoadProfile,
+    /// Ignored for the last stage, which keeps going
+    #[serde(default)]
+    pub duration_secs: u64,
+}
+
+/// Limits a run has to stay within, the run fails if one is breached
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Thresholds {
+    /// Highest share of sent txs the relayer may reject, `0.01` is 1%
+    pub max_error_rate: Option<f64>,
+    /// Highest share of submitted jobs that may fail or revert
+    pub max_failed_rate: Option<f64>,
+    /// Highest corrected job latency percentiles in seconds
+    pub max_p50_latency: Option<f64>,
+    pub max_p90_latency: Option<f64>,
+    pub max_p99_latency: Option<f64>,
+    /// Lowest number of completed jobs
+    pub min_completed: Option<usize>,
+}
+
+impl Thresholds {
+    /// Descriptions of the breached thresholds, empty if the run passed
+    pub fn check(&self, summary: &RunSummary) -> Vec<String> {
+        let mut breaches = vec![];
+        let mut check = |name: &str, value: f64, limit: Option<f64>, above: bool| {
+            if let Some(limit) = limit {
+                if (above && value > limit) || (!above && value < limit) {
+                    breaches.push(format!("{} {:.3}, limit {:.3}", name, value, limit));
+                }
+            }
+        };
+
+        let rate = |count: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                count as f64 / total as f64
+            }
+        };
+        let state = |name: &str| summary.jobs.get(name).copied().unwrap_or_default();
+        let latency = &summary.corrected_latency;
+
+        check(
+            "error rate",
+            rate(summary.rejected, summary.sent),
+            self.max_error_rate,
+            true,
+        );
+        check(
+            "failed rate",
+            rate(state("failed") + state("reverted"), summary.submitted),
+            self.max_failed_rate,
+            true,
+        );
+        check("p50 latency", latency.p50, self.max_p50_latency, true);
+        check("p90 latency", latency.p90, self.max_p90_latency, true);
+        check("p99 latency", latency.p99, self.max_p99_latency, true);
+        check(
+            "completed jobs",
+            state("completed") as f64,
+            self.min_completed.map(|min| min as f64),
+            false,
+        );
+
+        breaches
+    }
+}
+
+/// Files a run writes
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Outputs {
+    #[serde(default = "default_result_log")]
+    pub result_log: String,
+    #[serde(default = "default_jobs_log")]
+    pub jobs_log: String,
+    /// JSON summary of the run
+    pub summary: Option<String>,
+    /// Push the relayer processing times of finished jobs to the Prometheus push gateway
+    #[serde(default)]
+    pub publish: bool,
+}
+
+impl Default for Outputs {
+    fn default() -> Self {
+        Outputs {
+            result_log: default_result_log(),
+            jobs_log: default_jobs_log(),
+            summary: None,
+            publish: false,
+        }
+    }
+}
+
+fn default_result_log() -> String {
+    String::from("result.log")
+}
+
+fn default_jobs_log() -> String {
+    String::from("jobs.log")
+}
+
+/// Outcome of a send run
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunSummary {
+    pub total: usize,
+    pub sent: usize,
+    pub submitted: usize,
+    pub rejected: usize,
+    /// Submissions still waiting for the relayer at the deadline
+    pub in_flight: usize,
+    /// Rejected txs by cause
+    pub errors: BTreeMap<String, usize>,
+    /// Tracked jobs by final state
+    pub jobs: BTreeMap<String, usize>,
+    /// Seconds
+    pub elapsed: f64,
+    /// Job latency from submission to the terminal state
+    pub latency: Quantiles,
+    /// Job latency from the scheduled send time to the terminal state
+    pub corrected_latency: Quantiles,
+    /// Outcome by transaction type
+    pub types: BTreeMap<String, TypeSummary>,
+    /// Proving and queueing of generated transactions
+    pub pipeline: PipelineSummary,
+}
+
+/// Outcome of the txs of one type
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeSummary {
+    pub submitted: usize,
+    pub rejected: usize,
+    pub latency: Quantiles,
+    pub corrected_latency: Quantiles,
+}
+
+/// Values given as strings, or as numbers where TOML and YAML would read them as such
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Text {
+    String(String),
+    Number(u64),
+}
+
+impl Text {
+    fn parse<T: FromStr, E: de::Error>(self) -> Result<T, E>
+    where
+        T::Err: Display,
+    {
+        let text = match self {
+            Text::String(text) => text,
+            Text::Number(number) => number.to_string(),
+        };
+        text.parse().map_err(E::custom)
+    }
+}
+
+fn parse<'de, D: Deserializer<'de>, T: FromStr>(deserializer: D) -> Result<T, D::Error>
+where
+    T::Err: Display,
+{
+    Text::deserialize(deserializer)?.parse()
+}
+
+fn parse_option<'de, D: Deserializer<'de>, T: FromStr>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error>
+where
+    T::Err: Display,
+{
+    Option::<Text>::deserialize(deserializer)?
+        .map(Text::parse)
+        .transpose()
+}
+
+#[test]
+fn example_scenario_test() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios/example.toml");
+    let scenario = Scenario::load(path).unwrap();
+
+    assert_eq!(scenario.txs.len(), 2);
+    assert_eq!(scenario.txs[1].tx_type, TxKind::Transfer);
+    assert_eq!(
+        scenario.txs[0].amount,
+        Some(AmountRange {
+            min: 1_000_000_000,
+            max: 2_000_000_000
+        })
+    );
+    assert!(matches!(
+        scenario.load.profile().unwrap(),
+        Some(LoadProfile::Stages(stages)) if stages.len() == 2
+    ));
+    assert_eq!(scenario.relayer.retry.policy().unwrap().max_attempts, 3);
+    assert!(scenario.relayer.http_config(None).unwrap().build().is_ok());
+}
+
+#[test]
+fn yaml_scenario_test() {
+    let scenario: Scenario = serde_yaml::from_str(
+        "
+name: closed
+txs:
+  - type: deposit
+    count: 10
+    amount: 5
+load:
+  users: 4
+thresholds:
+  max_error_rate: 0.01
+  min_completed: 10
+",
+    )
+    .unwrap();
+    assert_eq!(scenario.load.profile().unwrap(), None);
+    assert_eq!(scenario.mix().unwrap(), None);
+    assert_eq!(scenario.txs[0].amount, Some(AmountRange { min: 5, max: 5 }));
+
+    let quantiles = Quantiles {
+        count: 10,
+        p50: 1.0,
+        p90: 2.0,
+        p99: 3.0,
+        max: 4.0,
+    };
+    let summary = RunSummary {
+        total: 10,
+        sent: 10,
+        submitted: 9,
+        rejected: 1,
+        in_flight: 0,
+        errors: BTreeMap::from([(String::from("timeout"), 1)]),
+        jobs: BTreeMap::from([(String::from("completed"), 9)]),
+        elapsed: 5.0,
+        latency: quantiles,
+        corrected_latency: quantiles,
+        types: BTreeMap::new(),
+        pipeline: PipelineSummary::default(),
+    };
+    assert_eq!(
+        scenario.thresholds.check(&summary),
+        vec![
+            "error rate 0.100, limit 0.010",
+            "completed jobs 9.000, limit 10.000"
+        ]
+    );
+
+    let invalid = "name: both\nload:\n  users: 1\n  profile: constant:1\n";
+    let scenario: Scenario = serde_yaml::from_str(invalid).unwrap();
+    assert!(scenario.load.profile().is_err());
+    assert!(serde_yaml::from_str::<Scenario>("name: typo\nlaod: {}\n").is_err());
+
+    let mixed = |last_weight: &str| {
+        serde_yaml::from_str::<Scenario>(&format!(
+            "
+name: mixed
+txs:
+  - {{ type: transfer, count: 60, weight: 6 }}
+  - {{ type: deposit, count: 30, weight: 2 }}
+  - {{ type: deposit, count: 10{} }}
+load:
+  users: 1
+",
+            last_weight
+        ))
+        .unwrap()
+        .mix()
+    };
+    assert_eq!(
+        mixed(", weight: 1").unwrap(),
+        Some("transfer:6,deposit:3".parse().unwrap())
+    );
+    assert!(mixed("").is_err());
+}
@@ -0,0 +1,397 @@
+//! Transaction building and proving with injected randomness. libzeropool-rs draws note
+//! blinding, diversifiers and memo encryption entropy from the OS and fawkes-crypto draws the
+//! Groth16 blinding factors from it too, so the same seed would still give different files.
+//! These mirror `UserAccount::create_tx` and `prove_tx` with a caller provided RNG instead.
+
+use std::convert::TryInto;
+
+use kvdb_memorydb::InMemory as MemoryDatabase;
+use libzeropool::{
+    circuit::tx::c_transfer,
+    constants,
+    fawkes_crypto::{
+        backend::bellman_groth16::{
+            engines::{Bn256, Engine},
+            num_to_bellman_fp,
+            prover::Proof,
+            BellmanCS, Parameters,
+        },
+        circuit::{
+            cs::{WitnessCS, CS},
+            lc::Index,
+        },
+        core::{signal::Signal, sizedvec::SizedVec},
+        engines::bn256::Fr,
+        ff_uint::{Num, NumRepr, Uint},
+        native::poseidon::MerkleProof,
+        rand::Rng,
+    },
+    native::{
+        account::Account,
+        boundednum::BoundedNum,
+        cipher,
+        key::derive_key_p_d,
+        note::Note,
+        params::PoolBN256,
+        tx::{
+            make_delta, nullifier, out_commitment_hash, tx_hash, tx_sign, TransferPub, TransferSec,
+            Tx,
+        },
+    },
+    POOL_PARAMS,
+};
+use libzeropool_rs::{
+    address::{format_address, parse_address},
+    client::{TransactionData, TxType, UserAccount},
+};
+use web3::signing::keccak256;
+
+use crate::utils::TestError;
+
+type Diversifier = BoundedNum<Fr, { constants::DIVERSIFIER_SIZE_BITS }>;
+
+/// Public inputs together with the proof
+type Proved<E> = (Vec<Num<<E as Engine>::Fr>>, Proof<E>);
+
+/// A new address of `acc`, like `UserAccount::generate_address`
+pub fn generate_address<R: Rng>(
+    acc: &UserAccount<MemoryDatabase, PoolBN256>,
+    rng: &mut R,
+) -> String {
+    let (d, p_d) = address_components(acc, rng);
+    format_address::<PoolBN256>(d, p_d)
+}
+
+fn address_components<R: Rng>(
+    acc: &UserAccount<MemoryDatabase, PoolBN256>,
+    rng: &mut R,
+) -> (Diversifier, Num<Fr>) {
+    let d: Diversifier = rng.gen();
+    let p_d = derive_key_p_d(d.to_num(), acc.keys.eta, &acc.params).x;
+    (d, p_d)
+}
+
+/// `UserAccount::create_tx` for an account without any account or note in the pool yet, the
+/// throwaway accounts of the generator
+pub fn create_tx<R: Rng>(
+    acc: &UserAccount<MemoryDatabase, PoolBN256>,
+    tx: TxType<Fr>,
+    rng: &mut R,
+) -> Result<TransactionData<Fr>, TestError> {
+    let state = &acc.state;
+    let (keys, params) = (&acc.keys, &acc.params);
+    if state.latest_account_index.is_some() || state.total_balance() != Num::ZERO {
+        return Err(TestError::GeneratorError(String::from(
+            "seeded transactions are only built for fresh accounts",
+        )));
+    }
+
+    let to_u64 = |amount: Num<Fr>| -> u64 { amount.try_into().unwrap() };
+    let (fee, tx_data, user_data) = match &tx {
+        TxType::Deposit(fee, user_data, _) | TxType::Transfer(fee, user_data, _) => {
+            (fee, to_u64(fee.to_num()).to_be_bytes().to_vec(), user_data)
+        }
+        TxType::Withdraw(fee, user_data, _, receiver, native_amount, _) => {
+            let mut tx_data = to_u64(fee.to_num()).to_be_bytes().to_vec();
+            tx_data.extend(to_u64(native_amount.to_num()).to_be_bytes());
+            tx_data.extend(receiver);
+            (fee, tx_data, user_data)
+        }
+    };
+
+    // the initial account has d = pool_id to protect from replay attacks
+    let in_account = Account {
+        d: acc.pool_id,
+        p_d: derive_key_p_d(acc.pool_id.to_num(), keys.eta, params).x,
+        i: BoundedNum::new(Num::ZERO),
+        b: BoundedNum::new(Num::ZERO),
+        e: BoundedNum::new(Num::ZERO),
+    };
+    let spend_interval_index = match state.latest_note_index {
+        0 => 0,
+        index => index + 1,
+    };
+
+    let mut output_value = Num::ZERO;
+    let (num_real_out_notes, out_notes): (usize, SizedVec<Note<Fr>, { constants::OUT }>) = match &tx
+    {
+        TxType::Transfer(_, _, outputs) => {
+            if outputs.len() >= constants::OUT {
+                return Err(TestError::GeneratorError(format!(
+                    "too many outputs: expected {} max, got {}",
+                    constants::OUT - 1,
+                    outputs.len()
+                )));
+            }
+            let mut notes = Vec::with_capacity(constants::OUT);
+            for output in outputs {
+                let (d, p_d) = parse_address::<PoolBN256>(&output.to)
+                    .map_err(|e| TestError::GeneratorError(e.to_string()))?;
+                output_value += output.amount.to_num();
+                notes.push(Note {
+                    d,
+                    p_d,
+                    b: output.amount,
+                    t: rng.gen(),
+                });
+            }
+            notes.resize_with(constants::OUT, zero_note);
+            (outputs.len(), notes.into_iter().collect())
+        }
+        _ => (0, (0..constants::OUT).map(|_| zero_note()).collect()),
+    };
+
+    let mut delta_value = -fee.as_num();
+    let mut delta_energy = Num::ZERO;
+    let delta_index = Num::from(state.tree.next_index());
+    let input_value = in_account.b.to_num();
+    let input_energy = in_account.e.to_num() + in_account.b.to_num() * delta_index;
+
+    let insufficient = |needed: Num<Fr>| {
+        TestError::GeneratorError(format!(
+            "insufficient balance: {} needed, {} available",
+            needed, input_value
+        ))
+    };
+    let new_balance = match &tx {
+        TxType::Transfer(..) => {
+            if input_value.to_uint() < output_value.to_uint() {
+                return Err(insufficient(output_value));
+            }
+            input_value - output_value
+        }
+        TxType::Withdraw(_, _, amount, _, _, energy) => {
+            if energy.to_num().to_uint() > input_energy.to_uint() {
+                return Err(TestError::GeneratorError(format!(
+                    "insufficient energy: {} needed, {} available",
+                    energy.to_num(),
+                    input_energy
+                )));
+            }
+            delta_energy -= energy.to_num();
+            delta_value -= amount.to_num();
+            if input_value.to_uint() < amount.to_num().to_uint() {
+                return Err(insufficient(amount.to_num()));
+            }
+            input_value + delta_value
+        }
+        TxType::Deposit(_, _, amount) => {
+            delta_value += amount.to_num();
+            input_value + delta_value
+        }
+    };
+
+    let (d, p_d) = address_components(acc, rng);
+    let out_account = Account {
+        d,
+        p_d,
+        i: BoundedNum::new(Num::from(spend_interval_index)),
+        b: BoundedNum::new(new_balance),
+        e: BoundedNum::new(delta_energy + input_energy),
+    };
+
+    let in_account_hash = in_account.hash(params);
+    let nullifier = nullifier(in_account_hash, keys.eta, Num::ZERO, params);
+
+    let entropy: [u8; 32] = rng.gen();
+    let ciphertext = cipher::encrypt(
+        &entropy,
+        keys.eta,
+        out_account,
+        &out_notes[0..num_real_out_notes],
+        params,
+    );
+
+    // the account owns no notes, the inputs are zero notes of its own
+    let in_notes: SizedVec<Note<Fr>, { constants::IN }> = (0..constants::IN)
+        .map(|_| {
+            let (d, p_d) = address_components(acc, rng);
+            Note {
+                d,
+                p_d,
+                b: BoundedNum::new(Num::ZERO),
+                t: rng.gen(),
+            }
+        })
+        .collect();
+    let input_hashes: SizedVec<Num<Fr>, { constants::IN + 1 }> = std::iter::once(in_account_hash)
+        .chain(in_notes.iter().map(|note| note.hash(params)))
+        .collect();
+    let out_hashes: SizedVec<Num<Fr>, { constants::OUT + 1 }> =
+        std::iter::once(out_account.hash(params))
+            .chain(out_notes.iter().map(|note| note.hash(params)))
+            .collect();
+
+    let out_commit = out_commitment_hash(out_hashes.as_slice(), params);
+    let tx_hash = tx_hash(input_hashes.as_slice(), out_commit, params);
+    let delta = make_delta::<Fr>(delta_value, delta_energy, delta_index, acc.pool_id.to_num());
+
+    // memo = tx specific data, ciphertext, user defined data
+    let mut memo = tx_data;
+    memo.extend(&ciphertext);
+    memo.extend(user_data);
+    let memo_hash = keccak256(&memo);
+
+    let public = TransferPub {
+        root: state.tree.get_root(),
+        nullifier,
+        out_commit,
+        delta,
+        memo: Num::from_uint_reduced(NumRepr(Uint::from_big_endian(&memo_hash))),
+    };
+
+    let (eddsa_s, eddsa_r) = tx_sign(keys.sk, tx_hash, params);
+    let secret = TransferSec {
+        tx: Tx {
+            input: (in_account, in_notes),
+            output: (out_account, out_notes),
+        },
+        in_proof: (
+            zero_proof(),
+            (0..constants::IN).map(|_| zero_proof()).collect(),
+        ),
+        eddsa_s: eddsa_s.to_other().unwrap(),
+        eddsa_r,
+        eddsa_a: keys.a,
+    };
+
+    Ok(TransactionData {
+        public,
+        secret,
+        ciphertext,
+        memo,
+        commitment_root: out_commit,
+        out_hashes,
+    })
+}
+
+/// `prove_tx` with the Groth16 blinding factors drawn from `rng`
+pub fn prove_tx<R: Rng>(
+    params: &Parameters<Bn256>,
+    public: &TransferPub<Fr>,
+    secret: &TransferSec<Fr>,
+    rng: &mut R,
+) -> Result<Proved<Bn256>, TestError> {
+    prove(
+        params,
+        public,
+        secret,
+        |public, secret| c_transfer(&public, &secret, &*POOL_PARAMS),
+        rng,
+    )
+}
+
+/// fawkes-crypto `prove` with the blinding factors drawn from `rng`
+pub fn prove<'a, E, Pub, Sec, C, R>(
+    params: &'a Parameters<E>,
+    input_pub: &Pub::Value,
+    input_sec: &Sec::Value,
+    circuit: C,
+    rng: &mut R,
+) -> Result<Proved<E>, TestError>
+where
+    E: Engine,
+    Pub: Signal<WitnessCS<'a, E::Fr>>,
+    Sec: Signal<WitnessCS<'a, E::Fr>>,
+    C: Fn(Pub, Sec),
+    R: Rng,
+{
+    let rcs = &params.get_witness_rcs();
+    let signal_pub = Pub::alloc(rcs, Some(input_pub));
+    signal_pub.inputize();
+    let signal_sec = Sec::alloc(rcs, Some(input_sec));
+
+    circuit(signal_pub, signal_sec);
+
+    let bcs = BellmanCS::<E, WitnessCS<E::Fr>>::new(rcs.clone());
+    let r = num_to_bellman_fp(rng.gen::<Num<E::Fr>>());
+    let s = num_to_bellman_fp(rng.gen::<Num<E::Fr>>());
+    let proof = bellman::groth16::create_proof(bcs, &params.0, r, s)
+        .map_err(|e| TestError::GeneratorError(format!("proving failed: {}", e)))?;
+
+    let cs = rcs.borrow();
+    let inputs = (1..cs.num_input() as u32)
+        .map(|i| cs.get_value(Index::Input(i)).unwrap())
+        .collect();
+
+    Ok((inputs, Proof::from_bellman(&proof)))
+}
+
+fn zero_note() -> Note<Fr> {
+    Note {
+        d: BoundedNum::new(Num::ZERO),
+        p_d: Num::ZERO,
+        b: BoundedNum::new(Num::ZERO),
+        t: BoundedNum::new(Num::ZERO),
+    }
+}
+
+fn zero_proof() -> MerkleProof<Fr, { constants::HEIGHT }> {
+    MerkleProof {
+        sibling: (0..constants::HEIGHT).map(|_| Num::ZERO).collect(),
+        path: (0..constants::HEIGHT).map(|_| false).collect(),
+    }
+}
+
+#[test]
+fn seeded_proof_test() {
+    use libzeropool::fawkes_crypto::{
+        backend::bellman_groth16::{setup::setup, verifier::verify},
+        circuit::num::CNum,
+        rand::{rngs::StdRng, SeedableRng},
+    };
+
+    fn circuit<C: CS>(public: CNum<C>, secret: CNum<C>) {
+        secret.square().assert_eq(&public);
+    }
+    let params = setup::<Bn256, _, _, _>(circuit);
+
+    let secret = Num::from(7);
+    let public = secret * secret;
+    let prove_with = |seed: u64| {
+        let rng = &mut StdRng::seed_from_u64(seed);
+        let (inputs, proof) = prove(&params, &public, &secret, circuit, rng).unwrap();
+        assert!(verify(&params.get_vk(), &proof, &inputs));
+        serde_json::to_string(&proof).unwrap()
+    };
+
+    assert_eq!(prove_with(1), prove_with(1));
+    assert_ne!(prove_with(1), prove_with(2));
+}
+
+#[test]
+fn seeded_create_tx_test() {
+    use libzeropool::{
+        fawkes_crypto::rand::{rngs::StdRng, SeedableRng},
+        POOL_PARAMS,
+    };
+    use libzeropool_rs::client::state::State;
+
+    let acc = UserAccount::new(
+        Num::from(42),
+        State::init_test(POOL_PARAMS.clone()),
+        POOL_PARAMS.clone(),
+    );
+    let deposit = || {
+        TxType::Deposit(
+            BoundedNum::new(Num::from(10)),
+            vec![1, 2, 3],
+            BoundedNum::new(Num::from(1000)),
+        )
+    };
+    let seeded = create_tx(&acc, deposit(), &mut StdRng::seed_from_u64(1)).unwrap();
+    let library = acc.create_tx(deposit(), None).unwrap();
+
+    // everything but the randomness matches libzeropool-rs
+    assert_eq!(seeded.public.root, library.public.root);
+    assert_eq!(seeded.public.nullifier, library.public.nullifier);
+    assert_eq!(seeded.public.delta, library.public.delta);
+    assert_eq!(seeded.memo.len(), library.memo.len());
+    assert_eq!(seeded.memo[..8], library.memo[..8]);
+    let (seeded_out, library_out) = (&seeded.secret.tx.output.0, &library.secret.tx.output.0);
+    assert_eq!(
+        (seeded_out.i, seeded_out.b, seeded_out.e),
+        (library_out.i, library_out.b, library_out.e)
+    );
+    assert_eq!(seeded.secret.eddsa_a, library.secret.eddsa_a);
+}
@@ -1,4 +1,10 @@
 use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     thread,
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -6,31 +12,348 @@ use std::{
 use reqwest::StatusCode;
 use serde::{Serialize, Deserialize};
 
-use crate::{generator::Deposit, utils::TestError};
+use crate::{
+    client::Relayer,
+    generator::{Deposit, TxKind},
+    latency::Latencies,
+    pipeline::QueueReceiver,
+    retry::RetryPolicy,
+    utils::TestError,
+};
 
 use tokio::{
     sync::mpsc::Sender,
-    time::{sleep, Duration},
+    time::{sleep, Duration, Instant},
 };
 
 #[derive(Debug,Serialize,Deserialize)]
 pub struct JobResult{
     pub job_id: u32,
-    file_name: String,
-    created: SystemTime
+    pub file_name: String,
+    /// `None` if the transaction type is unknown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_type: Option<TxKind>,
+    pub created: SystemTime,
+    /// When the load profile scheduled the transaction, `None` if sending wasn't paced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled: Option<SystemTime>,
+    /// Timing of the `POST /transaction` that created the job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpTiming>,
+    /// Number of `POST /transaction` attempts, 0 if unknown
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+impl JobResult {
+    pub fn new(job_id: u32, file_name: String) -> Self {
+        JobResult {
+            job_id,
+            file_name,
+            tx_type: None,
+            created: SystemTime::now(),
+            scheduled: None,
+            http: None,
+            attempts: 1,
+        }
+    }
+
+    pub fn with_tx_type(mut self, tx_type: Option<TxKind>) -> Self {
+        self.tx_type = tx_type;
+        self
+    }
+
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    pub fn with_schedule(mut self, scheduled: Option<SystemTime>) -> Self {
+        self.scheduled = scheduled;
+        self
+    }
+
+    pub fn with_http(mut self, http: HttpTiming) -> Self {
+        self.http = Some(http);
+        self
+    }
+}
+
+/// Submission the relayer didn't turn into a job
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FailedSubmission {
+    pub file_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_type: Option<TxKind>,
+    pub created: SystemTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled: Option<SystemTime>,
+    pub http: HttpTiming,
+    #[serde(default)]
+    pub attempts: u32,
+    pub error: ErrorKind,
+    pub message: String,
+}
+
+/// Outcome of a `POST /transaction`, one line of `result.log`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SubmitResult {
+    Job(JobResult),
+    Failed(FailedSubmission),
+}
+
+impl SubmitResult {
+    pub fn new(
+        submission: Submission,
+        file_name: String,
+        tx_type: Option<TxKind>,
+        scheduled: Option<SystemTime>,
+    ) -> Self {
+        let Submission {
+            result,
+            http,
+            attempts,
+        } = submission;
+        match result {
+            Ok(job_id) => SubmitResult::Job(
+                JobResult::new(job_id, file_name)
+                    .with_tx_type(tx_type)
+                    .with_schedule(scheduled)
+                    .with_http(http)
+                    .with_attempts(attempts),
+            ),
+            Err(e) => SubmitResult::Failed(FailedSubmission {
+                file_name,
+                tx_type,
+                created: SystemTime::now(),
+                scheduled,
+                error: ErrorKind::classify(&e, http.status),
+                message: e.to_string(),
+                http,
+                attempts,
+            }),
+        }
+    }
+
+    pub fn file_name(&self) -> &str {
+        match self {
+            SubmitResult::Job(job) => &job.file_name,
+            SubmitResult::Failed(failed) => &failed.file_name,
+        }
+    }
+
+    pub fn tx_type(&self) -> Option<TxKind> {
+        match self {
+            SubmitResult::Job(job) => job.tx_type,
+            SubmitResult::Failed(failed) => failed.tx_type,
+        }
+    }
+
+    fn scheduled(&self) -> Option<SystemTime> {
+        match self {
+            SubmitResult::Job(job) => job.scheduled,
+            SubmitResult::Failed(failed) => failed.scheduled,
+        }
+    }
+
+    fn http(&self) -> Option<&HttpTiming> {
+        match self {
+            SubmitResult::Job(job) => job.http.as_ref(),
+            SubmitResult::Failed(failed) => Some(&failed.http),
+        }
+    }
+}
+
+/// Why a submission failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// No response before the request timeout
+    Timeout,
+    /// The relayer couldn't be reached
+    Connect,
+    /// The relayer rejected the proof
+    InvalidProof,
+    /// The nullifier was spent already
+    DuplicateNullifier,
+    /// The depositor lacks token balance or allowance
+    InsufficientBalance,
+    /// Any other error status
+    Http,
+    /// A successful status with a body the runner doesn't understand
+    BadResponse,
+    /// The request didn't go out, e.g. the transaction didn't serialize
+    Other,
 }
 
+impl ErrorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Connect => "connect",
+            ErrorKind::InvalidProof => "invalid-proof",
+            ErrorKind::DuplicateNullifier => "duplicate-nullifier",
+            ErrorKind::InsufficientBalance => "insufficient-balance",
+            ErrorKind::Http => "http",
+            ErrorKind::BadResponse => "bad-response",
+            ErrorKind::Other => "other",
+        }
+    }
+
+    /// Classifies a submission error, `status` is the HTTP status if a response arrived.
+    /// Relayer errors are told apart by their message.
+    pub fn classify(error: &TestError, status: Option<u16>) -> Self {
+        match (error, status) {
+            (TestError::NetworkError(e), _) if e.is_timeout() => ErrorKind::Timeout,
+            (TestError::NetworkError(e), _) if e.is_connect() => ErrorKind::Connect,
+            (_, Some(200)) => ErrorKind::BadResponse,
+            (TestError::BadResponse(message), Some(_)) => {
+                let message = message.to_lowercase();
+                if message.contains("proof") {
+                    ErrorKind::InvalidProof
+                } else if message.contains("nullifier") || message.contains("double spend") {
+                    ErrorKind::DuplicateNullifier
+                } else if message.contains("balance") || message.contains("allowance") {
+                    ErrorKind::InsufficientBalance
+                } else {
+                    ErrorKind::Http
+                }
+            }
+            (_, Some(_)) => ErrorKind::Http,
+            (_, None) => ErrorKind::Other,
+        }
+    }
+}
+
+impl FromStr for ErrorKind {
+    type Err = TestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            ErrorKind::Timeout,
+            ErrorKind::Connect,
+            ErrorKind::InvalidProof,
+            ErrorKind::DuplicateNullifier,
+            ErrorKind::InsufficientBalance,
+            ErrorKind::Http,
+            ErrorKind::BadResponse,
+            ErrorKind::Other,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == s)
+        .ok_or_else(|| TestError::ConfigError(format!("unknown error kind {}", s)))
+    }
+}
+
+/// Client side timing of a relayer request. With retries it spans every attempt, the first
+/// byte and the status are those of the last one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpTiming {
+    pub started: SystemTime,
+    pub finished: SystemTime,
+    /// Seconds from `started` until the response headers arrived, `None` if no response
+    /// arrived
+    pub first_byte: Option<f64>,
+    /// HTTP status, `None` if no response arrived
+    pub status: Option<u16>,
+}
+
+impl HttpTiming {
+    /// Seconds from the start of the request until the response was read or the request failed
+    pub fn duration(&self) -> f64 {
+        self.finished
+            .duration_since(self.started)
+            .map_or(0.0, |duration| duration.as_secs_f64())
+    }
+}
+
+/// Relayer job as returned by `/job/{id}`. Lifecycle timestamps are milliseconds since the
+/// epoch and are only present once the relayer reports them, `created` is the only one the
+/// relayer sends today.
 #[derive(Debug,Deserialize)]
 pub struct JobStatus {
 #[serde(rename(deserialize="state"))]   
- _state: String,
- #[serde(rename(deserialize="txHash"))]
- _tx_hash: String,
- #[serde(rename(deserialize="created"))]
- _created: u64,
+ pub state: String,
+ #[serde(rename(deserialize="txHash"), default)]
+ pub tx_hash: Option<String>,
+ /// The relayer received the transaction
+ #[serde(default)]
+ pub created: Option<u64>,
+ /// The relayer started processing the job
+ #[serde(default)]
+ pub processing: Option<u64>,
+ /// The proof was verified
+ #[serde(default)]
+ pub verified: Option<u64>,
+ /// The pool transaction was sent to the chain
+ #[serde(default)]
+ pub sent: Option<u64>,
+ /// The pool transaction was mined
+ #[serde(default)]
+ pub mined: Option<u64>,
+ #[serde(default)]
 pub elapsed: u32
 }
 
+impl JobStatus {
+    pub fn is_completed(&self) -> bool {
+        self.job_state() == JobState::Completed
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self.job_state(), JobState::Failed | JobState::Reverted)
+    }
+
+    pub fn job_state(&self) -> JobState {
+        match self.state.as_str() {
+            "waiting" | "delayed" | "paused" | "queued" => JobState::Queued,
+            "active" | "processing" => JobState::Processing,
+            "sent" => JobState::Sent,
+            "completed" | "mined" => JobState::Completed,
+            "failed" => JobState::Failed,
+            "reverted" => JobState::Reverted,
+            _ => JobState::Unknown,
+        }
+    }
+}
+
+/// Relayer job lifecycle. Bull queue states are mapped onto it: a waiting job is queued, an
+/// active one is being processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Processing,
+    Sent,
+    Completed,
+    Failed,
+    Reverted,
+    Unknown,
+}
+
+impl JobState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Processing => "processing",
+            JobState::Sent => "sent",
+            JobState::Completed => "completed",
+            JobState::Failed => "failed",
+            JobState::Reverted => "reverted",
+            JobState::Unknown => "unknown",
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            JobState::Completed | JobState::Failed | JobState::Reverted
+        )
+    }
+}
+
 #[derive(Debug,Deserialize)]
 struct RelayerReponse {
     #[serde(rename(deserialize = "jobId"))]
@@ -59,44 +382,475 @@ pub async fn emulate_send(_: Deposit) -> Result<(), TestError> {
     Ok(())
 }
 
-pub async fn send_tx(
-    file_name: String,
-    deposit: Deposit,
-    mpsc_sender: Sender<JobResult>,
-    relayer_url: String
+/// Counters shared by the send tasks of a run
+#[derive(Debug, Default)]
+pub struct SendStats {
+    /// Transactions whose submission started
+    pub sent: AtomicUsize,
+    /// Transactions the relayer accepted
+    pub submitted: AtomicUsize,
+    /// Transactions that didn't make it to a relayer job
+    pub rejected: AtomicUsize,
+    /// Rejected transactions by cause
+    pub errors: Mutex<BTreeMap<ErrorKind, usize>>,
+    /// Client side `POST /transaction` latencies by response status
+    pub http_latencies: Mutex<BTreeMap<Option<u16>, Latencies>>,
+    /// Submissions by transaction type, those of unknown type aren't counted
+    pub by_type: Mutex<BTreeMap<TxKind, TypeStats>>,
+}
+
+/// Submissions of one transaction type
+#[derive(Debug, Clone, Default)]
+pub struct TypeStats {
+    pub submitted: usize,
+    pub rejected: usize,
+    /// Client side `POST /transaction` latencies
+    pub http_latencies: Latencies,
+}
+
+impl SendStats {
+    fn record(&self, result: &SubmitResult) {
+        if let Some(tx_type) = result.tx_type() {
+            let mut by_type = self.by_type.lock().unwrap();
+            let stats = by_type.entry(tx_type).or_default();
+            match result {
+                SubmitResult::Job(_) => stats.submitted += 1,
+                SubmitResult::Failed(_) => stats.rejected += 1,
+            }
+            if let Some(http) = result.http() {
+                stats
+                    .http_latencies
+                    .record(result.scheduled(), http.started, http.finished);
+            }
+        }
+
+        match result {
+            SubmitResult::Job(_) => {
+                self.submitted.fetch_add(1, Ordering::Relaxed);
+            }
+            SubmitResult::Failed(failed) => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                *self.errors.lock().unwrap().entry(failed.error).or_default() += 1;
+            }
+        }
+        if let Some(http) = result.http() {
+            self.http_latencies
+                .lock()
+                .unwrap()
+                .entry(http.status)
+                .or_default()
+                .record(result.scheduled(), http.started, http.finished);
+        }
+    }
+}
+
+/// Transaction read from the corpus, waiting to be submitted
+#[derive(Debug, Clone)]
+pub struct PendingTx<T> {
+    pub file_name: String,
+    /// `None` if the transaction type is unknown
+    pub tx_type: Option<TxKind>,
+    pub tx: T,
+}
+
+/// Submits a transaction, `scheduled` is when the load profile meant it to go out
+pub async fn send_tx<T: Serialize>(
+    pending: PendingTx<T>,
+    scheduled: Option<SystemTime>,
+    mpsc_sender: Sender<SubmitResult>,
+    relayer: Relayer,
+    stats: Arc<SendStats>,
 ) -> () {
-    let client = reqwest::Client::new();
+    let PendingTx {
+        file_name,
+        tx_type,
+        tx,
+    } = pending;
+    stats.sent.fetch_add(1, Ordering::Relaxed);
+    let submission = submit_tx_timed(&relayer.client, &relayer.url, &tx, &relayer.retry).await;
+    let result = SubmitResult::new(submission, file_name, tx_type, scheduled);
+    stats.record(&result);
+    if let SubmitResult::Failed(failed) = &result {
+        tracing::error!(
+            "{} rejected ({}) after {} attempts and {:.3}s: {}",
+            failed.file_name,
+            failed.error.name(),
+            failed.attempts,
+            failed.http.duration(),
+            failed.message
+        );
+    }
+    if mpsc_sender.send(result).await.is_err() {
+        tracing::error!("can't record the submission result");
+    }
+}
+
+/// Result of a `POST /transaction`, retries included
+pub struct Submission {
+    pub result: Result<u32, TestError>,
+    pub http: HttpTiming,
+    pub attempts: u32,
+}
+
+/// Posts a transaction to the relayer and returns the id of the created job
+pub async fn submit_tx<T: Serialize>(
+    client: &reqwest::Client,
+    relayer_url: &str,
+    tx: &T,
+    retry: &RetryPolicy,
+) -> Result<u32, TestError> {
+    submit_tx_timed(client, relayer_url, tx, retry).await.result
+}
+
+/// [`submit_tx`] along with the client side timing of the request
+pub async fn submit_tx_timed<T: Serialize>(
+    client: &reqwest::Client,
+    relayer_url: &str,
+    tx: &T,
+    retry: &RetryPolicy,
+) -> Submission {
+    let started = SystemTime::now();
+    let clock = Instant::now();
+    let mut attempts = 0;
+
+    loop {
+        attempts += 1;
+        let mut first_byte = None;
+        let mut status = None;
 
-    let body = serde_json::to_string(&deposit).unwrap();
+        let result = async {
+            let body = serde_json::to_string(tx).map_err(TestError::SerializationError)?;
 
-    tracing::trace!("tx body:\n{}", body);
+            tracing::trace!("tx body:\n{}", body);
 
-    let result = client
-        .post(format!("{}/transaction",relayer_url))
-        .body(body)
-        .header("Content-type", "application/json")
-        .timeout(Duration::from_secs(5))
-        .send()
-        .await
-        .unwrap();
-        // .map_err(|e| TestError::NetworkError(e))?;
+            let response = client
+                .post(format!("{}/transaction", relayer_url))
+                .body(body)
+                .header("Content-type", "application/json")
+                .send()
+                .await?;
+            first_byte = Some(clock.elapsed().as_secs_f64());
+            status = Some(response.status().as_u16());
 
+            job_id(response).await
+        }
+        .await;
+
+        if let Err(e) = &result {
+            if let Some(delay) = retry.retry_after(e, status, attempts) {
+                tracing::warn!("attempt {} failed, retrying in {:?}: {}", attempts, delay, e);
+                sleep(delay).await;
+                continue;
+            }
+        }
+
+        let http = HttpTiming {
+            started,
+            finished: started + clock.elapsed(),
+            first_byte,
+            status,
+        };
+        return Submission {
+            result,
+            http,
+            attempts,
+        };
+    }
+}
+
+async fn job_id(result: reqwest::Response) -> Result<u32, TestError> {
     match result.status() {
         StatusCode::OK => {
-        
-            let response =result.json::<RelayerReponse>().await.unwrap();
+            let response = result.json::<RelayerReponse>().await?;
             tracing::debug!("tx response {:#?}", response);
-            let job_id: u32 = response.job_id.parse::<u32>().unwrap();
-            mpsc_sender
-                .send(JobResult{job_id, file_name, created:SystemTime::now() })
-                .await
-                .unwrap();
-            
+            response
+                .job_id
+                .parse::<u32>()
+                .map_err(|e| TestError::BadResponse(format!("invalid job id: {}", e)))
+        }
+        _ => Err(TestError::BadResponse(result.text().await?)),
+    }
+}
+
+pub async fn job_status(
+    client: &reqwest::Client,
+    relayer_url: &str,
+    job_id: u32,
+    retry: &RetryPolicy,
+) -> Result<JobStatus, TestError> {
+    let mut attempts = 0;
+
+    loop {
+        attempts += 1;
+        let mut status = None;
+
+        let result = async {
+            let response = client
+                .get(format!("{}/job/{}", relayer_url, job_id))
+                .send()
+                .await?;
+            status = Some(response.status().as_u16());
+            if !response.status().is_success() {
+                return Err(TestError::BadResponse(response.text().await?));
+            }
+
+            Ok(response.json().await?)
         }
-        _ => {
-            let response = result.text().await.unwrap();
-            tracing::error!("something wrong happened {}", response);
-            
+        .await;
+
+        match result {
+            Err(e) => match retry.retry_after(&e, status, attempts) {
+                Some(delay) => {
+                    tracing::debug!("polling job {} failed, retrying in {:?}: {}", job_id, delay, e);
+                    sleep(delay).await;
+                }
+                None => return Err(e),
+            },
+            result => return result,
         }
     }
 }
+
+/// Polls the relayer until the job is either completed or failed
+pub async fn wait_for_job(
+    client: &reqwest::Client,
+    relayer_url: &str,
+    job_id: u32,
+    timeout: Duration,
+    retry: &RetryPolicy,
+) -> Result<JobStatus, TestError> {
+    let started = tokio::time::Instant::now();
+    loop {
+        let status = job_status(client, relayer_url, job_id, retry).await?;
+        if status.job_state().is_terminal() {
+            return Ok(status);
+        }
+        if started.elapsed() > timeout {
+            return Err(TestError::BadResponse(format!(
+                "job {} is still {} after {:?}",
+                job_id, status.state, timeout
+            )));
+        }
+        sleep(Duration::from_millis(1000)).await;
+    }
+}
+
+/// Closed-model virtual user: sends the next transaction from the shared queue only once the
+/// relayer job of the previous one is completed or failed, until the queue is closed.
+pub async fn run_virtual_user<T: Serialize>(
+    user: usize,
+    txs: Arc<tokio::sync::Mutex<QueueReceiver<PendingTx<T>>>>,
+    mpsc_sender: Sender<SubmitResult>,
+    relayer: Relayer,
+    job_timeout: Duration,
+    stats: Arc<SendStats>,
+) {
+    let Relayer { client, url: relayer_url, retry } = relayer;
+
+    loop {
+        // every tx is due as soon as the user is ready for it
+        let due = Instant::now();
+        let next = txs.lock().await.take(due).await;
+        let PendingTx {
+            file_name,
+            tx_type,
+            tx,
+        } = match next {
+            Some(next) => next,
+            None => break,
+        };
+
+        stats.sent.fetch_add(1, Ordering::Relaxed);
+        let submission = submit_tx_timed(&client, &relayer_url, &tx, &retry).await;
+        let result = SubmitResult::new(submission, file_name, tx_type, None);
+        stats.record(&result);
+        let job_id = match &result {
+            SubmitResult::Job(job) => Some(job.job_id),
+            SubmitResult::Failed(failed) => {
+                tracing::error!(
+                    "user {} failed to send {} ({}): {}",
+                    user,
+                    failed.file_name,
+                    failed.error.name(),
+                    failed.message
+                );
+                None
+            }
+        };
+        if mpsc_sender.send(result).await.is_err() {
+            tracing::error!("user {} can't record the submission result", user);
+        }
+        let job_id = match job_id {
+            Some(job_id) => job_id,
+            None => continue,
+        };
+
+        match wait_for_job(&client, &relayer_url, job_id, job_timeout, &retry).await {
+            Ok(status) => tracing::info!("user {} job {} {}", user, job_id, status.state),
+            Err(e) => tracing::error!("user {} gave up on job {}: {:?}", user, job_id, e),
+        }
+    }
+
+    tracing::debug!("user {} done", user);
+}
+
+#[test]
+fn virtual_user_waits_for_jobs_test() {
+    use crate::test_relayer::TestRelayer;
+
+    let requests = Arc::new(Mutex::new(vec![]));
+    let log = requests.clone();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let relayer = TestRelayer::start(move |method, path, _| {
+            let mut log = log.lock().unwrap();
+            log.push(format!("{} {}", method, path));
+            match method {
+                "POST" => {
+                    let job_id = log.iter().filter(|r| r.starts_with("POST")).count();
+                    (200, format!(r#"{{"jobId":"{}"}}"#, job_id))
+                }
+                _ => (200, String::from(r#"{"state":"completed","elapsed":10}"#)),
+            }
+        })
+        .await;
+
+        let (txs, queue) = crate::pipeline::queue(2, Default::default());
+        for i in 0..2 {
+            let tx = PendingTx {
+                file_name: i.to_string(),
+                tx_type: Some(TxKind::Transfer),
+                tx: i,
+            };
+            assert!(txs.send(tx).await);
+        }
+        drop(txs);
+        let (results_sender, mut results) = tokio::sync::mpsc::channel(10);
+        run_virtual_user(
+            0,
+            Arc::new(tokio::sync::Mutex::new(queue)),
+            results_sender,
+            Relayer {
+                client: reqwest::Client::new(),
+                url: relayer.url(),
+                retry: RetryPolicy::default(),
+            },
+            Duration::from_secs(5),
+            Arc::new(SendStats::default()),
+        )
+        .await;
+
+        let first = match results.recv().await.unwrap() {
+            SubmitResult::Job(job) => job,
+            failed => panic!("unexpected {:?}", failed),
+        };
+        assert_eq!(first.job_id, 1);
+        assert_eq!(first.tx_type, Some(TxKind::Transfer));
+        let http = first.http.unwrap();
+        assert_eq!(http.status, Some(200));
+        assert!(http.first_byte.unwrap() <= http.duration());
+        assert!(matches!(
+            results.recv().await.unwrap(),
+            SubmitResult::Job(JobResult { job_id: 2, .. })
+        ));
+    });
+
+    assert_eq!(
+        *requests.lock().unwrap(),
+        vec!["POST /transaction", "GET /job/1", "POST /transaction", "GET /job/2"]
+    );
+}
+
+#[test]
+fn rejected_submission_test() {
+    use crate::test_relayer::TestRelayer;
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let relayer = TestRelayer::start(|_, _, body| match body {
+            "1" => (500, String::from(r#"{"error":"Double spend"}"#)),
+            _ => (400, String::from(r#"{"error":"Invalid proof"}"#)),
+        })
+        .await;
+
+        let stats = Arc::new(SendStats::default());
+        let (results_sender, mut results) = tokio::sync::mpsc::channel(10);
+        for tx in 1..=2 {
+            send_tx(
+                PendingTx {
+                    file_name: tx.to_string(),
+                    tx_type: Some(TxKind::Deposit),
+                    tx,
+                },
+                None,
+                results_sender.clone(),
+                Relayer {
+                    client: reqwest::Client::new(),
+                    url: relayer.url(),
+                    retry: RetryPolicy::default(),
+                },
+                stats.clone(),
+            )
+            .await;
+        }
+
+        let result = results.recv().await.unwrap();
+        let line = serde_json::to_string(&result).unwrap();
+        match serde_json::from_str::<SubmitResult>(&line).unwrap() {
+            SubmitResult::Failed(failed) => {
+                assert_eq!(failed.error, ErrorKind::DuplicateNullifier);
+                assert_eq!(failed.tx_type, Some(TxKind::Deposit));
+                assert_eq!(failed.http.status, Some(500));
+            }
+            job => panic!("unexpected {:?}", job),
+        }
+
+        assert_eq!(stats.rejected.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.by_type.lock().unwrap()[&TxKind::Deposit].rejected, 2);
+        assert_eq!(
+            *stats.errors.lock().unwrap(),
+            BTreeMap::from([(ErrorKind::InvalidProof, 1), (ErrorKind::DuplicateNullifier, 1)])
+        );
+
+        let client = reqwest::Client::new();
+        let refused =
+            submit_tx_timed(&client, "http://127.0.0.1:1", &0, &RetryPolicy::default()).await;
+        assert_eq!(
+            ErrorKind::classify(&refused.result.unwrap_err(), refused.http.status),
+            ErrorKind::Connect
+        );
+    });
+}
+
+#[test]
+fn submit_retry_test() {
+    use crate::test_relayer::TestRelayer;
+
+    let posts = Arc::new(AtomicUsize::new(0));
+    let counter = posts.clone();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let submission = rt.block_on(async {
+        let relayer = TestRelayer::start(move |_, _, _| {
+            match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => (429, String::from(r#"{"error":"Too many requests"}"#)),
+                1 => (503, String::from(r#"{"error":"Service unavailable"}"#)),
+                _ => (200, String::from(r#"{"jobId":"7"}"#)),
+            }
+        })
+        .await;
+
+        let retry = RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        submit_tx_timed(&reqwest::Client::new(), &relayer.url(), &0, &retry).await
+    });
+
+    assert_eq!(submission.result.unwrap(), 7);
+    assert_eq!(submission.attempts, 3);
+    assert_eq!(submission.http.status, Some(200));
+    assert_eq!(posts.load(Ordering::SeqCst), 3);
+}
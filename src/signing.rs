@@ -0,0 +1,73 @@
+//! Local Ethereum message signing, equivalent to `web3.eth.accounts.sign` but without a node.
+
+use lazy_static::lazy_static;
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    All, Message, PublicKey, Secp256k1, SecretKey,
+};
+use web3::signing::{hash_message, keccak256};
+
+use crate::utils::TestError;
+
+lazy_static! {
+    pub(crate) static ref SECP256K1: Secp256k1<All> = Secp256k1::new();
+}
+
+/// Recoverable ECDSA signature in Ethereum `v, r, s` form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub v: u64,
+    pub r: [u8; 32],
+    pub s: [u8; 32],
+}
+
+/// Signs `message` prefixed with "\x19Ethereum Signed Message:\n32"
+pub fn sign(sk: &SecretKey, message: [u8; 32]) -> Signature {
+    let hash = hash_message(message);
+    let msg = Message::from_slice(hash.as_bytes()).expect("message hash is 32 bytes");
+
+    let (recovery_id, compact) = SECP256K1
+        .sign_ecdsa_recoverable(&msg, sk)
+        .serialize_compact();
+
+    let mut r = [0u8; 32];
+    let mut s = [0u8; 32];
+    r.copy_from_slice(&compact[..32]);
+    s.copy_from_slice(&compact[32..]);
+
+    Signature {
+        v: recovery_id.to_i32() as u64 + 27,
+        r,
+        s,
+    }
+}
+
+/// Recovers the address that signed `message` with [`sign`]
+pub fn recover(message: [u8; 32], signature: &Signature) -> Result<[u8; 20], TestError> {
+    let invalid = |e: secp256k1::Error| TestError::GeneratorError(format!("invalid signature: {}", e));
+
+    let hash = hash_message(message);
+    let msg = Message::from_slice(hash.as_bytes()).expect("message hash is 32 bytes");
+
+    let recovery_id = RecoveryId::from_i32(signature.v as i32 - 27).map_err(invalid)?;
+    let compact = [signature.r, signature.s].concat();
+    let signature = RecoverableSignature::from_compact(&compact, recovery_id).map_err(invalid)?;
+
+    let public_key = SECP256K1.recover_ecdsa(&msg, &signature).map_err(invalid)?;
+
+    Ok(public_key_address(&public_key))
+}
+
+/// Ethereum address of a secret key
+pub fn address(sk: &SecretKey) -> [u8; 20] {
+    public_key_address(&PublicKey::from_secret_key(&SECP256K1, sk))
+}
+
+fn public_key_address(public_key: &PublicKey) -> [u8; 20] {
+    let serialized = public_key.serialize_uncompressed();
+    let hash = keccak256(&serialized[1..]);
+
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    address
+}
@@ -0,0 +1,342 @@
+use borsh::BorshDeserialize;
+use kvdb::KeyValueDB;
+use kvdb_memorydb::InMemory as MemoryDatabase;
+use libzeropool::{
+    constants,
+    fawkes_crypto::{engines::bn256::Fr, ff_uint::Num},
+    native::{cipher, note::Note, params::PoolBN256},
+    POOL_PARAMS,
+};
+use libzeropool_rs::client::state::State;
+use serde::{Deserialize, Deserializer};
+
+use crate::utils::TestError;
+
+/// Number of relayer transactions requested per `/transactions` call
+const SYNC_BATCH: usize = 100;
+
+/// Pool state as reported by the relayer `/info` endpoint
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayerInfo {
+    pub root: Num<Fr>,
+    pub optimistic_root: Num<Fr>,
+    #[serde(deserialize_with = "index")]
+    pub delta_index: u64,
+    #[serde(deserialize_with = "index")]
+    pub optimistic_delta_index: u64,
+}
+
+/// The relayer reports indices either as numbers or as decimal strings
+fn index<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    use serde::de::Error;
+
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(n) => n
+            .as_u64()
+            .ok_or_else(|| D::Error::custom("index is not an unsigned integer")),
+        serde_json::Value::String(s) => s.parse().map_err(D::Error::custom),
+        v => Err(D::Error::custom(format!("unexpected index {}", v))),
+    }
+}
+
+/// Client for the relayer transaction feed. Replays committed transactions into a local
+/// `State` so proofs are built against the live pool root.
+#[derive(Clone)]
+pub struct PoolSync {
+    client: reqwest::Client,
+    relayer_url: String,
+}
+
+impl PoolSync {
+    /// Talks to the relayer with `client`, shared with the rest of the run
+    pub fn new(client: reqwest::Client, relayer_url: String) -> Self {
+        PoolSync {
+            client,
+            relayer_url,
+        }
+    }
+
+    pub async fn info(&self) -> Result<RelayerInfo, TestError> {
+        let info = self
+            .client
+            .get(format!("{}/info", self.relayer_url))
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        Ok(info)
+    }
+
+    /// Adds every transaction following the local tree to the state and checks the resulting
+    /// root against the relayer. When `eta` is set, the account and notes it owns are stored
+    /// too, so they can be spent later.
+    pub async fn sync<D: KeyValueDB>(
+        &self,
+        state: &mut State<D, PoolBN256>,
+        eta: Option<Num<Fr>>,
+    ) -> Result<(), TestError> {
+        loop {
+            let offset = state.tree.next_index();
+            let txs: Vec<String> = self
+                .client
+                .get(format!(
+                    "{}/transactions/{}/{}",
+                    self.relayer_url, SYNC_BATCH, offset
+                ))
+                .send()
+                .await?
+                .json()
+                .await?;
+
+            for (i, tx) in txs.iter().enumerate() {
+                let index = offset + (i * (constants::OUT + 1)) as u64;
+                add_tx(state, eta, index, tx)?;
+            }
+
+            if txs.len() < SYNC_BATCH {
+                break;
+            }
+        }
+
+        self.verify_root(state).await
+    }
+
+    /// Syncs a keyless state and keeps only what is needed to build transactions from
+    /// fresh accounts on top of it.
+    pub async fn snapshot(&self) -> Result<PoolSnapshot, TestError> {
+        let mut state = State::init_test(POOL_PARAMS.clone());
+        self.sync(&mut state, None).await?;
+
+        let snapshot = PoolSnapshot::from_state(&state);
+        tracing::info!(
+            "synced pool state, next index {}, root {}",
+            snapshot.next_index(),
+            state.tree.get_root()
+        );
+
+        Ok(snapshot)
+    }
+
+    async fn verify_root<D: KeyValueDB>(
+        &self,
+        state: &State<D, PoolBN256>,
+    ) -> Result<(), TestError> {
+        let info = self.info().await?;
+        let next_index = state.tree.next_index();
+
+        let expected = if next_index == info.optimistic_delta_index {
+            info.optimistic_root
+        } else if next_index == info.delta_index {
+            info.root
+        } else {
+            // the pool moved on while we were syncing, nothing to compare with
+            tracing::debug!(
+                "skipping root check: local index {}, relayer index {}",
+                next_index,
+                info.optimistic_delta_index
+            );
+            return Ok(());
+        };
+
+        let root = state.tree.get_root();
+        if root != expected {
+            return Err(TestError::BadResponse(format!(
+                "local root {} at index {} doesn't match relayer root {}",
+                root, next_index, expected
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds a transaction from the relayer feed at `index`
+pub fn add_tx<D: KeyValueDB>(
+    state: &mut State<D, PoolBN256>,
+    eta: Option<Num<Fr>>,
+    index: u64,
+    tx: &str,
+) -> Result<(), TestError> {
+    // out commitment (32 bytes) | tx hash (32 bytes) | memo
+    let memo = tx
+        .get(128..)
+        .and_then(|memo| hex::decode(memo).ok())
+        .ok_or_else(|| TestError::BadResponse(format!("malformed tx at index {}", index)))?;
+
+    let hashes = parse_hashes(&memo)
+        .ok_or_else(|| TestError::BadResponse(format!("malformed memo at index {}", index)))?;
+
+    match eta {
+        Some(eta) => {
+            let account = cipher::decrypt_out(eta, &memo, &*POOL_PARAMS).map(|(acc, _)| acc);
+            let notes: Vec<(u64, Note<Fr>)> = cipher::decrypt_in(eta, &memo, &*POOL_PARAMS)
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, note)| note.map(|note| (index + 1 + i as u64, note)))
+                .collect();
+
+            state.add_full_tx(index, &hashes, account, &notes);
+        }
+        None => state.add_hashes(index, &hashes),
+    }
+
+    Ok(())
+}
+
+/// Reads the account and note hashes prepended to the memo ciphertext
+fn parse_hashes(mut memo: &[u8]) -> Option<Vec<Num<Fr>>> {
+    let count = <u32 as BorshDeserialize>::deserialize(&mut memo).ok()?;
+    (0..count)
+        .map(|_| <Num<Fr> as BorshDeserialize>::deserialize(&mut memo).ok())
+        .collect()
+}
+
+/// Left frontier of the pool tree: the roots of the complete subtrees covering every
+/// committed leaf. It is enough to rebuild the pool root and the next index in a fresh
+/// tree, which is all a new account needs to create a transaction.
+#[derive(Debug, Clone)]
+pub struct PoolSnapshot {
+    frontier: Vec<(u32, u64, Num<Fr>)>,
+    next_index: u64,
+}
+
+impl PoolSnapshot {
+    pub fn from_state<D: KeyValueDB>(state: &State<D, PoolBN256>) -> Self {
+        let next_index = state.tree.next_index();
+        let frontier = (constants::OUTPLUSONELOG as u32..=constants::HEIGHT as u32)
+            .rev()
+            .filter(|height| next_index & (1 << height) != 0)
+            .map(|height| {
+                let index = (next_index >> height) - 1;
+                (height, index, state.tree.get(height, index))
+            })
+            .collect();
+
+        PoolSnapshot {
+            frontier,
+            next_index,
+        }
+    }
+
+    pub fn next_index(&self) -> u64 {
+        self.next_index
+    }
+
+    /// Fresh state whose tree has the same root and next index as the synced pool
+    pub fn state(&self) -> State<MemoryDatabase, PoolBN256> {
+        let mut state = State::init_test(POOL_PARAMS.clone());
+        for (height, index, hash) in &self.frontier {
+            state.tree.add_hash_at_height(*height, *index, *hash, false);
+        }
+
+        state
+    }
+}
+
+#[cfg(test)]
+fn test_feed_entry(memo: &[u8]) -> String {
+    // the relayer strips the 8-byte fee prefix from deposit and transfer memos
+    format!(
+        "{}{}{}",
+        "00".repeat(32),
+        "00".repeat(32),
+        hex::encode(&memo[8..])
+    )
+}
+
+#[test]
+fn snapshot_root_test() {
+    use libzeropool::native::boundednum::BoundedNum;
+    use libzeropool_rs::client::{TxType, UserAccount};
+
+    let mut state = State::init_test(POOL_PARAMS.clone());
+    for i in 0..5u64 {
+        let acc = UserAccount::new(
+            Num::from(i + 1),
+            State::init_test(POOL_PARAMS.clone()),
+            POOL_PARAMS.clone(),
+        );
+        let tx_data = acc
+            .create_tx(
+                TxType::Deposit(
+                    BoundedNum::new(Num::ZERO),
+                    vec![],
+                    BoundedNum::new(Num::ONE),
+                ),
+                None,
+            )
+            .unwrap();
+        add_tx(
+            &mut state,
+            None,
+            i * (constants::OUT + 1) as u64,
+            &test_feed_entry(&tx_data.memo),
+        )
+        .unwrap();
+    }
+
+    let restored = PoolSnapshot::from_state(&state).state();
+
+    assert_eq!(restored.tree.next_index(), state.tree.next_index());
+    assert_eq!(restored.tree.get_root(), state.tree.get_root());
+}
+
+#[test]
+fn sync_from_relayer_test() {
+    use crate::test_relayer::TestRelayer;
+    use libzeropool::native::boundednum::BoundedNum;
+    use libzeropool_rs::{
+        client::{TxType, UserAccount},
+        keys::Keys,
+    };
+
+    let sk = Num::from(42u64);
+    let acc = UserAccount::new(sk, State::init_test(POOL_PARAMS.clone()), POOL_PARAMS.clone());
+    let tx_data = acc
+        .create_tx(
+            TxType::Deposit(
+                BoundedNum::new(Num::ZERO),
+                vec![],
+                BoundedNum::new(Num::from(7u64)),
+            ),
+            None,
+        )
+        .unwrap();
+
+    let mut expected = State::init_test(POOL_PARAMS.clone());
+    expected.add_hashes(0, tx_data.out_hashes.as_slice());
+
+    let feed = serde_json::to_string(&vec![test_feed_entry(&tx_data.memo)]).unwrap();
+    let info = format!(
+        r#"{{"root":"{}","optimisticRoot":"{}","deltaIndex":"{}","optimisticDeltaIndex":{}}}"#,
+        expected.tree.get_root(),
+        expected.tree.get_root(),
+        constants::OUT + 1,
+        constants::OUT + 1
+    );
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let relayer = TestRelayer::start(move |_, path, _| match path {
+            "/transactions/100/0" => (200, feed.clone()),
+            "/info" => (200, info.clone()),
+            _ => (200, String::from("[]")),
+        })
+        .await;
+
+        let mut state = State::init_test(POOL_PARAMS.clone());
+        let eta = Keys::derive(sk, &*POOL_PARAMS).eta;
+        PoolSync::new(reqwest::Client::new(), relayer.url())
+            .sync(&mut state, Some(eta))
+            .await
+            .unwrap();
+
+        assert_eq!(state.tree.get_root(), expected.tree.get_root());
+        assert_eq!(state.total_balance(), Num::from(7u64));
+
+        let snapshot = PoolSync::new(reqwest::Client::new(), relayer.url()).snapshot().await.unwrap();
+        assert_eq!(snapshot.state().tree.get_root(), expected.tree.get_root());
+    });
+}
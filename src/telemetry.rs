@@ -15,12 +15,10 @@ pub fn get_subscriber(
 
     let formatting_layer = BunyanFormattingLayer::new(name, sink);
 
-    let subscriber = Registry::default()
+    Registry::default()
         .with(env_filter)
         .with(JsonStorageLayer)
-        .with(formatting_layer);
-
-    subscriber
+        .with(formatting_layer)
 }
 
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
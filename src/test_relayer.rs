@@ -0,0 +1,93 @@
+//! Minimal HTTP stand-in for the relayer used by tests. Every request is answered by the
+//! handler with a status code and a JSON body, the connection is closed afterwards.
+
+use std::sync::Arc;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+type Handler = dyn Fn(&str, &str, &str) -> (u16, String) + Send + Sync;
+
+pub struct TestRelayer {
+    url: String,
+}
+
+impl TestRelayer {
+    /// Starts serving on a random local port. The handler receives the method, the path and
+    /// the request body.
+    pub async fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&str, &str, &str) -> (u16, String) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, handler).await {
+                        tracing::debug!("test relayer connection failed: {}", e);
+                    }
+                });
+            }
+        });
+
+        TestRelayer { url }
+    }
+
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+}
+
+async fn serve(mut stream: TcpStream, handler: Arc<Handler>) -> std::io::Result<()> {
+    let mut request = vec![];
+    let mut buf = [0u8; 4096];
+
+    let header_end = loop {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..read]);
+        if let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&request[..header_end]).into_owned();
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+
+    while request.len() < header_end + content_length {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default();
+    let body = String::from_utf8_lossy(&request[header_end..]);
+
+    let (status, response) = handler(method, path, &body);
+
+    let response = format!(
+        "HTTP/1.1 {} OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        response.len(),
+        response
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,536 @@
+//! Live job tracking: every submitted job is polled while the run goes on, so latencies are
+//! known as soon as jobs finish instead of in a separate `publish` pass.
+
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    generator::TxKind,
+    latency::{Latencies, LatencyHistogram},
+    retry::RetryPolicy,
+};
+
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::mpsc::{error::TryRecvError, Receiver, Sender},
+    time::{self, Duration, Instant},
+};
+
+use crate::sender::{job_status, JobResult, JobState, JobStatus};
+
+/// State the job was seen in and when it was first seen in it. Timestamps are accurate to the
+/// poll interval.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    pub state: JobState,
+    pub at: SystemTime,
+}
+
+/// Lifecycle of a relayer job from submission to its terminal state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackedJob {
+    pub job_id: u32,
+    pub file_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_type: Option<TxKind>,
+    /// Start of the request that submitted the job
+    pub submitted: SystemTime,
+    /// When the load profile scheduled the transaction, `None` if sending wasn't paced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled: Option<SystemTime>,
+    pub transitions: Vec<Transition>,
+    /// Seconds from submission to the terminal state, `None` if the job didn't get there
+    /// before the timeout
+    pub latency: Option<f64>,
+    /// Processing time reported by the relayer, in milliseconds
+    pub relayer_elapsed: u32,
+    /// Lifecycle timestamps reported by the relayer
+    #[serde(default)]
+    pub relayer_times: RelayerTimes,
+}
+
+/// Job lifecycle timestamps reported by the relayer, in milliseconds since the epoch
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RelayerTimes {
+    pub received: Option<u64>,
+    #[serde(default)]
+    pub processing: Option<u64>,
+    pub verified: Option<u64>,
+    pub sent: Option<u64>,
+    pub mined: Option<u64>,
+    pub tx_hash: Option<String>,
+}
+
+impl RelayerTimes {
+    fn update(&mut self, status: &JobStatus) {
+        self.received = status.created.or(self.received);
+        self.processing = status.processing.or(self.processing);
+        self.verified = status.verified.or(self.verified);
+        self.sent = status.sent.or(self.sent);
+        self.mined = status.mined.or(self.mined);
+        self.tx_hash = status.tx_hash.clone().or_else(|| self.tx_hash.take());
+    }
+}
+
+/// Stages of a job, named after the event that ends them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    /// Client submission until the relayer received the transaction. Includes the clock skew
+    /// between the client and the relayer.
+    Received,
+    /// Until the relayer started processing the job, its job queue
+    Processing,
+    /// Until the proof was verified
+    Verified,
+    /// Until the pool transaction was sent, the relayer send queue
+    Sent,
+    /// Until the pool transaction was mined, chain inclusion
+    Mined,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::Received,
+        Stage::Processing,
+        Stage::Verified,
+        Stage::Sent,
+        Stage::Mined,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Received => "received",
+            Stage::Processing => "processing",
+            Stage::Verified => "verified",
+            Stage::Sent => "sent",
+            Stage::Mined => "mined",
+        }
+    }
+}
+
+impl TrackedJob {
+    fn new(job: JobResult) -> Self {
+        TrackedJob {
+            job_id: job.job_id,
+            file_name: job.file_name,
+            tx_type: job.tx_type,
+            submitted: job.http.as_ref().map_or(job.created, |http| http.started),
+            scheduled: job.scheduled,
+            transitions: vec![],
+            latency: None,
+            relayer_elapsed: 0,
+            relayer_times: RelayerTimes::default(),
+        }
+    }
+
+    fn first_seen(&self, state: JobState) -> Option<SystemTime> {
+        self.transitions
+            .iter()
+            .find(|transition| transition.state == state)
+            .map(|transition| transition.at)
+    }
+
+    /// Seconds spent in every stage whose end is known, from the end of the last known stage
+    /// before it, so a stage without timestamp is counted in the next one. Timestamps the
+    /// relayer doesn't report are taken from the polled state changes.
+    pub fn stages(&self) -> Vec<(Stage, f64)> {
+        let relayer = |ms: Option<u64>| ms.map(|ms| UNIX_EPOCH + Duration::from_millis(ms));
+        let times = &self.relayer_times;
+        let mined = match self.state() {
+            Some(JobState::Completed) => {
+                relayer(times.mined).or_else(|| self.first_seen(JobState::Completed))
+            }
+            _ => None,
+        };
+        let ends = [
+            relayer(times.received),
+            relayer(times.processing).or_else(|| self.first_seen(JobState::Processing)),
+            relayer(times.verified),
+            relayer(times.sent).or_else(|| self.first_seen(JobState::Sent)),
+            mined,
+        ];
+
+        let mut start = self.submitted;
+        Stage::ALL
+            .into_iter()
+            .zip(ends)
+            .filter_map(|(stage, end)| {
+                let end = end?;
+                let duration = end.duration_since(start).ok();
+                start = end;
+                duration.map(|duration| (stage, duration.as_secs_f64()))
+            })
+            .collect()
+    }
+
+    /// When the job was first seen in its terminal state
+    pub fn finished(&self) -> Option<SystemTime> {
+        self.transitions
+            .last()
+            .filter(|transition| transition.state.is_terminal())
+            .map(|transition| transition.at)
+    }
+
+    pub fn state(&self) -> Option<JobState> {
+        self.transitions.last().map(|transition| transition.state)
+    }
+
+    fn observe(&mut self, state: JobState, at: SystemTime) {
+        if self.state() == Some(state) {
+            return;
+        }
+        self.transitions.push(Transition { state, at });
+        if state.is_terminal() {
+            self.latency = at
+                .duration_since(self.submitted)
+                .ok()
+                .map(|latency| latency.as_secs_f64());
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobTracker {
+    client: reqwest::Client,
+    relayer_url: String,
+    poll_interval: Duration,
+    job_timeout: Duration,
+    retry: RetryPolicy,
+    concurrency: usize,
+}
+
+/// Jobs polled at a time by default
+pub const POLL_CONCURRENCY: usize = 32;
+
+impl JobTracker {
+    /// Polls with `client`, shared with the rest of the run
+    pub fn new(
+        client: reqwest::Client,
+        relayer_url: String,
+        poll_interval: Duration,
+        job_timeout: Duration,
+    ) -> Self {
+        JobTracker {
+            client,
+            relayer_url,
+            poll_interval,
+            job_timeout,
+            retry: RetryPolicy::default(),
+            concurrency: POLL_CONCURRENCY,
+        }
+    }
+
+    /// Retries of every poll, a poll that still fails is repeated on the next interval anyway
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Caps the polls in flight, so a large backlog of jobs doesn't flood the relayer
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Polls the jobs received from `submitted` until each one reaches a terminal state or
+    /// times out, then passes it to `finished`. Returns once `submitted` is closed and no job
+    /// is left, or at the deadline, passing on the jobs still pending as they were last seen.
+    pub async fn run(
+        self,
+        mut submitted: Receiver<JobResult>,
+        finished: Sender<TrackedJob>,
+        deadline: Option<Instant>,
+    ) {
+        let mut pending: Vec<(TrackedJob, Instant)> = vec![];
+        let mut closed = false;
+        let mut interval = time::interval(self.poll_interval);
+
+        while !closed || !pending.is_empty() {
+            interval.tick().await;
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return give_up(pending, &finished).await;
+            }
+
+            loop {
+                match submitted.try_recv() {
+                    Ok(job) => pending.push((TrackedJob::new(job), Instant::now())),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        closed = true;
+                        break;
+                    }
+                }
+            }
+
+            let job_ids: Vec<u32> = pending.iter().map(|(job, _)| job.job_id).collect();
+            let polls = stream::iter(job_ids)
+                .map(|job_id| job_status(&self.client, &self.relayer_url, job_id, &self.retry))
+                .buffered(self.concurrency)
+                .collect::<Vec<_>>();
+            // slow polls and their retries don't hold the run past the deadline
+            let statuses = match deadline {
+                Some(deadline) => match time::timeout_at(deadline, polls).await {
+                    Ok(statuses) => statuses,
+                    Err(_) => return give_up(pending, &finished).await,
+                },
+                None => polls.await,
+            };
+            let now = SystemTime::now();
+
+            let mut still_pending = vec![];
+            for ((mut job, tracked_since), status) in pending.into_iter().zip(statuses) {
+                match status {
+                    Ok(status) => {
+                        job.relayer_elapsed = status.elapsed;
+                        job.relayer_times.update(&status);
+                        job.observe(status.job_state(), now);
+                    }
+                    Err(e) => tracing::debug!("can't poll job {}: {}", job.job_id, e),
+                }
+
+                let done = job.state().is_some_and(|state| state.is_terminal());
+                if done || tracked_since.elapsed() > self.job_timeout {
+                    if !done {
+                        tracing::warn!(
+                            "job {} unfinished after {:?}",
+                            job.job_id,
+                            self.job_timeout
+                        );
+                    }
+                    if finished.send(job).await.is_err() {
+                        tracing::error!("nobody collects tracked jobs");
+                    }
+                } else {
+                    still_pending.push((job, tracked_since));
+                }
+            }
+            pending = still_pending;
+        }
+    }
+}
+
+/// Passes on the jobs still pending at the deadline as they were last seen
+async fn give_up(pending: Vec<(TrackedJob, Instant)>, finished: &Sender<TrackedJob>) {
+    tracing::warn!("deadline reached with {} jobs pending", pending.len());
+    for (job, _) in pending {
+        if finished.send(job).await.is_err() {
+            tracing::error!("nobody collects tracked jobs");
+        }
+    }
+}
+
+/// Latencies of finished jobs, end to end and per stage
+#[derive(Debug, Default)]
+pub struct StageLatencies {
+    /// Submission to terminal state
+    pub jobs: Latencies,
+    /// Submission to terminal state by transaction type
+    pub by_type: BTreeMap<TxKind, Latencies>,
+    samples: BTreeMap<Stage, LatencyHistogram>,
+}
+
+impl StageLatencies {
+    pub fn record(&mut self, job: &TrackedJob) {
+        if let Some(finished) = job.finished() {
+            self.jobs.record(job.scheduled, job.submitted, finished);
+            if let Some(tx_type) = job.tx_type {
+                self.by_type
+                    .entry(tx_type)
+                    .or_default()
+                    .record(job.scheduled, job.submitted, finished);
+            }
+        }
+        for (stage, latency) in job.stages() {
+            self.samples.entry(stage).or_default().record(latency);
+        }
+    }
+
+    /// `count`, `p50`, `p90`, `p99` and `max` in seconds for every stage with samples
+    pub fn report(&self) -> Vec<String> {
+        self.samples
+            .iter()
+            .map(|(stage, samples)| format!("{}: {}", stage.name(), samples.summary()))
+            .collect()
+    }
+}
+
+#[test]
+fn job_stages_test() {
+    let submitted = UNIX_EPOCH + Duration::from_secs(100);
+    let mut job = TrackedJob::new(JobResult {
+        job_id: 1,
+        file_name: String::new(),
+        tx_type: None,
+        created: submitted,
+        scheduled: None,
+        http: None,
+        attempts: 1,
+    });
+    job.relayer_times = RelayerTimes {
+        received: Some(100_500),
+        processing: None,
+        verified: Some(102_000),
+        sent: None,
+        mined: Some(110_000),
+        tx_hash: None,
+    };
+    // polled as active, then sent
+    job.observe(JobState::Processing, UNIX_EPOCH + Duration::from_secs(101));
+    job.observe(JobState::Sent, UNIX_EPOCH + Duration::from_secs(103));
+    job.observe(JobState::Completed, UNIX_EPOCH + Duration::from_secs(111));
+
+    assert_eq!(
+        job.stages(),
+        vec![
+            (Stage::Received, 0.5),
+            (Stage::Processing, 0.5),
+            (Stage::Verified, 1.0),
+            (Stage::Sent, 1.0),
+            (Stage::Mined, 7.0),
+        ]
+    );
+
+    let mut latencies = StageLatencies::default();
+    latencies.record(&job);
+    assert_eq!(
+        latencies.report()[4],
+        "mined: count 1, p50 7.000s, p90 7.000s, p99 7.000s, max 7.000s"
+    );
+
+    // without a verification time the send stage runs from the start of processing
+    job.relayer_times.verified = None;
+    assert_eq!(
+        job.stages(),
+        vec![
+            (Stage::Received, 0.5),
+            (Stage::Processing, 0.5),
+            (Stage::Sent, 2.0),
+            (Stage::Mined, 7.0),
+        ]
+    );
+}
+
+#[test]
+fn job_tracker_test() {
+    use crate::test_relayer::TestRelayer;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let counter = Arc::new(AtomicUsize::new(0));
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let relayer = TestRelayer::start(move |_, path, _| {
+            let state = match (path, counter.fetch_add(1, Ordering::SeqCst)) {
+                ("/job/2", _) => "failed",
+                (_, 0..=2) => "waiting",
+                (_, 3..=5) => "active",
+                _ => "completed",
+            };
+            (200, format!(r#"{{"state":"{}","elapsed":42}}"#, state))
+        })
+        .await;
+
+        let (submitted_sender, submitted) = tokio::sync::mpsc::channel(10);
+        let (finished_sender, mut finished) = tokio::sync::mpsc::channel(10);
+        let tracker = JobTracker::new(
+            reqwest::Client::new(),
+            relayer.url(),
+            Duration::from_millis(10),
+            Duration::from_secs(5),
+        );
+        let handle = tokio::spawn(tracker.run(submitted, finished_sender, None));
+
+        submitted_sender
+            .send(JobResult::new(1, String::from("1.json")))
+            .await
+            .unwrap();
+        submitted_sender
+            .send(JobResult::new(2, String::from("2.json")))
+            .await
+            .unwrap();
+        drop(submitted_sender);
+
+        let failed = finished.recv().await.unwrap();
+        assert_eq!(failed.job_id, 2);
+        assert_eq!(failed.state(), Some(JobState::Failed));
+
+        let completed = finished.recv().await.unwrap();
+        let states: Vec<_> = completed.transitions.iter().map(|t| t.state).collect();
+        assert_eq!(
+            states,
+            vec![JobState::Queued, JobState::Processing, JobState::Completed]
+        );
+        assert!(completed.latency.is_some());
+        assert_eq!(completed.relayer_elapsed, 42);
+
+        handle.await.unwrap();
+        assert!(finished.recv().await.is_none());
+    });
+}
+
+#[test]
+fn job_tracker_concurrency_test() {
+    use crate::test_relayer::TestRelayer;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+
+    // the test relayer blocks a worker for every request it holds
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(8)
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let (counted, max) = (in_flight.clone(), most.clone());
+        let relayer = TestRelayer::start(move |_, _, _| {
+            max.fetch_max(counted.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            counted.fetch_sub(1, Ordering::SeqCst);
+            (200, String::from(r#"{"state":"waiting","elapsed":0}"#))
+        })
+        .await;
+
+        let (submitted_sender, submitted) = tokio::sync::mpsc::channel(10);
+        let (finished_sender, mut finished) = tokio::sync::mpsc::channel(10);
+        let tracker = JobTracker::new(
+            reqwest::Client::new(),
+            relayer.url(),
+            Duration::from_millis(10),
+            Duration::from_secs(5),
+        )
+        .with_concurrency(2);
+        let deadline = Instant::now() + Duration::from_millis(250);
+        let handle = tokio::spawn(tracker.run(submitted, finished_sender, Some(deadline)));
+
+        for id in 0..8 {
+            submitted_sender
+                .send(JobResult::new(id, format!("{}.json", id)))
+                .await
+                .unwrap();
+        }
+
+        // the round of 8 polls takes 400ms, it is cut off at the deadline
+        handle.await.unwrap();
+        assert!(Instant::now() < deadline + Duration::from_millis(100));
+        let mut given_up = 0;
+        while finished.recv().await.is_some() {
+            given_up += 1;
+        }
+        assert_eq!(given_up, 8);
+    });
+
+    assert_eq!(most.load(Ordering::SeqCst), 2);
+}
@@ -7,6 +7,7 @@ pub enum TestError {
     ConfigError(String),
     BadResponse(String),
     MpscError,
+    ThresholdError(String),
 }
 
 impl From<reqwest::Error> for TestError {
@@ -22,7 +23,16 @@ impl From<std::io::Error> for TestError {
 
 impl std::fmt::Display for TestError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Adding a subscriber has failed")
+        match self {
+            TestError::NetworkError(e) => write!(f, "network error: {}", e),
+            TestError::GeneratorError(e) => write!(f, "generator error: {}", e),
+            TestError::FileAccessError(e) => write!(f, "file access error: {}", e),
+            TestError::SerializationError(e) => write!(f, "serialization error: {}", e),
+            TestError::ConfigError(e) => write!(f, "config error: {}", e),
+            TestError::BadResponse(e) => write!(f, "bad response: {}", e),
+            TestError::MpscError => write!(f, "result channel closed"),
+            TestError::ThresholdError(e) => write!(f, "thresholds breached: {}", e),
+        }
     }
 }
 
@@ -36,6 +46,7 @@ impl std::error::Error for TestError {
             TestError::ConfigError(_) => None,
             TestError::BadResponse(_) => None,
             TestError::MpscError => None,
+            TestError::ThresholdError(_) => None,
         }
     }
 }
\ No newline at end of file